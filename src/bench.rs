@@ -0,0 +1,507 @@
+//! The bench command, which times the scripts in test_files on both
+//! backends, and with Python for the ones that have a .py version, and
+//! compares the results against a baseline saved as JSON.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use slang_treewalk::{Backend, Interpreter};
+
+use crate::{read_source, UsageError};
+
+/// The scripts in test_files that get benchmarked
+const CORPUS: &[&str] = &[
+    "simple_bench",
+    "recursion01",
+    "fib_arr",
+    "project_euler_01",
+    "project_euler_02",
+    "project_euler_03",
+    "project_euler_04",
+    "project_euler_05",
+    "project_euler_06",
+];
+
+const DEFAULT_BASELINE: &str = "bench_baseline.json";
+
+// where the scripts are, relative to the working directory
+const DEFAULT_DIR: &str = "test_files";
+
+// the least time, in seconds, that each benchmark is run for
+const MIN_TIME: f64 = 0.5;
+
+// how much slower than the baseline a benchmark can get before it
+// counts as a regression, since timings are noisy
+const TOLERANCE: f64 = 0.10;
+
+// counts every allocation the program makes once the bench command
+// turns it on, so a benchmark's allocations are the difference between
+// the counts before and after. other commands only pay for checking
+// whether it's on
+struct CountingAlloc;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn count_allocation() {
+    if COUNTING.load(Ordering::Relaxed) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Measurement {
+    /// In seconds, for parsing, checking and running the script
+    time: f64,
+    allocations: u64,
+    /// Counted by the tree-walker, and None for the VM, which doesn't
+    /// count them
+    statements: Option<u64>,
+}
+
+struct Options {
+    runs: usize,
+    save: bool,
+    baseline: PathBuf,
+    dir: PathBuf,
+    names: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, UsageError> {
+    let mut opts = Options {
+        runs: 1,
+        save: false,
+        baseline: PathBuf::from(DEFAULT_BASELINE),
+        dir: PathBuf::from(DEFAULT_DIR),
+        names: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => {
+                opts.runs = match args.next().map(|n| n.parse()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => return Err(UsageError("--runs needs a positive number".to_string())),
+                }
+            }
+            "--save" => opts.save = true,
+            "--baseline" => match args.next() {
+                Some(file) => opts.baseline = PathBuf::from(file),
+                None => return Err(UsageError("--baseline needs a file".to_string())),
+            },
+            "--dir" => match args.next() {
+                Some(dir) => opts.dir = PathBuf::from(dir),
+                None => return Err(UsageError("--dir needs a directory".to_string())),
+            },
+            opt if opt.starts_with('-') => {
+                return Err(UsageError(format!("Unknown bench option {}", opt)))
+            }
+            name if CORPUS.contains(&name) => opts.names.push(name.to_string()),
+            name => {
+                return Err(UsageError(format!(
+                    "Unknown benchmark {}, the benchmarks are {}",
+                    name,
+                    CORPUS.join(", ")
+                )))
+            }
+        }
+    }
+    if opts.names.is_empty() {
+        opts.names = CORPUS.iter().map(|name| name.to_string()).collect();
+    }
+    Ok(opts)
+}
+
+fn measure(code: &str, path: &Path, backend: Backend) -> Result<Measurement, Box<dyn Error>> {
+    let mut interp = Interpreter::new();
+    interp.set_backend(backend);
+    // not counting the prelude's statements
+    if backend == Backend::TreeWalk {
+        interp.state_mut().steps = Some(0);
+    }
+    interp.state_mut().quiet = true;
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    interp.eval_str_in(code, path)?;
+    let time = start.elapsed().as_secs_f64();
+
+    Ok(Measurement {
+        time,
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        statements: interp.state().steps,
+    })
+}
+
+// the fastest of at least `runs` runs, which is the one with the least
+// noise. quick benchmarks are run until they've taken at least
+// `min_time` seconds, since one run of them is mostly noise
+fn best_of(
+    runs: usize,
+    min_time: f64,
+    mut run: impl FnMut() -> Result<Measurement, Box<dyn Error>>,
+) -> Result<Measurement, Box<dyn Error>> {
+    let mut best = run()?;
+    let mut total = best.time;
+    let mut n = 1;
+    while n < runs || total < min_time {
+        let m = run()?;
+        if m.time < best.time {
+            best = m;
+        }
+        total += m.time;
+        n += 1;
+    }
+    Ok(best)
+}
+
+// the script is timed inside Python, so that like with slang, starting
+// the interpreter up isn't part of it
+const PYTHON_TIMER: &str = "\
+import runpy, sys, time
+start = time.perf_counter()
+runpy.run_path(sys.argv[1], run_name='__main__')
+print(time.perf_counter() - start, file=sys.stderr)";
+
+fn time_python(script: &Path) -> Result<Measurement, Box<dyn Error>> {
+    let output = Command::new("python3")
+        .args(["-c", PYTHON_TIMER])
+        .arg(script)
+        .stdout(Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("python3 {} failed: {}", script.display(), stderr.trim()).into());
+    }
+    let time = stderr.lines().last().and_then(|line| line.parse().ok());
+    Ok(Measurement {
+        time: time.ok_or_else(|| format!("No time from python3 {}", script.display()))?,
+        ..Measurement::default()
+    })
+}
+
+fn per_second(count: Option<u64>, time: f64) -> String {
+    let rate = match count {
+        Some(count) => count as f64 / time,
+        None => return "n/a".to_string(),
+    };
+    if rate >= 1e6 {
+        format!("{:.2}M", rate / 1e6)
+    } else if rate >= 1e3 {
+        format!("{:.1}K", rate / 1e3)
+    } else {
+        format!("{:.0}", rate)
+    }
+}
+
+/// How a measurement compares to the baseline, and whether it's a
+/// regression.
+fn compare(m: &Measurement, base: &Measurement) -> (String, bool) {
+    let change = (m.time / base.time - 1.0) * 100.0;
+    let mut summary = format!("{:+.1}%", change);
+    let slower = m.time > base.time * (1.0 + TOLERANCE);
+    if slower {
+        summary.push_str(" slower");
+    }
+    // shown but not failed on, since they depend on the platform's
+    // allocator as much as on slang
+    if m.allocations != base.allocations {
+        summary.push_str(&format!(", {} allocations before", base.allocations));
+    }
+    (summary, slower)
+}
+
+fn row(name: &str, backend: &str, m: &Measurement, vs: &str) {
+    let time = format!("{:.2?}", Duration::from_secs_f64(m.time));
+    println!(
+        "{:<18} {:<10} {:>9} {:>12} {:>13}  {}",
+        name,
+        backend,
+        time,
+        m.allocations,
+        per_second(m.statements, m.time),
+        vs
+    );
+}
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let opts = parse_args(args)?;
+    if !opts.dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "There's no {} directory here, --dir <dir> says where the benchmarks are",
+                opts.dir.display()
+            ),
+        )
+        .into());
+    }
+    let mut baseline = if opts.baseline.exists() {
+        let json = read_source(&opts.baseline.to_string_lossy())?;
+        from_json(&json).map_err(|e| format!("Bad baseline {}: {}", opts.baseline.display(), e))?
+    } else {
+        if !opts.save {
+            println!(
+                "There's no baseline at {} to compare with, --save writes one\n",
+                opts.baseline.display()
+            );
+        }
+        BTreeMap::new()
+    };
+    let python = Command::new("python3")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok();
+
+    println!(
+        "{:<18} {:<10} {:>9} {:>12} {:>13}  vs baseline",
+        "benchmark", "backend", "time", "allocations", "statements/s"
+    );
+    let mut results = BTreeMap::new();
+    let mut regressions = Vec::new();
+    COUNTING.store(true, Ordering::Relaxed);
+    for name in &opts.names {
+        let path = opts.dir.join(format!("{}.slang", name));
+        let code = read_source(&path.to_string_lossy())?;
+
+        let tree_walk = best_of(opts.runs, MIN_TIME, || {
+            measure(&code, &path, Backend::TreeWalk)
+        })?;
+        let vm = best_of(opts.runs, MIN_TIME, || measure(&code, &path, Backend::Vm))?;
+
+        for (backend, m) in [("tree-walk", tree_walk), ("vm", vm)] {
+            let key = format!("{}/{}", name, backend);
+            let vs = match baseline.get(&key) {
+                Some(base) => {
+                    let (summary, regressed) = compare(&m, base);
+                    if regressed {
+                        regressions.push(key.clone());
+                    }
+                    summary
+                }
+                None => String::new(),
+            };
+            row(name, backend, &m, &vs);
+            results.insert(key, m);
+        }
+
+        let script = opts.dir.join(format!("{}.py", name));
+        if python && script.exists() {
+            let m = best_of(opts.runs, 0.0, || time_python(&script))?;
+            let time = format!("{:.2?}", Duration::from_secs_f64(m.time));
+            println!("{:<18} {:<10} {:>9}", name, "python", time);
+        }
+    }
+
+    if opts.save {
+        baseline.extend(results);
+        std::fs::write(&opts.baseline, to_json(&baseline))?;
+        println!("\nSaved the results to {}", opts.baseline.display());
+        Ok(())
+    } else if regressions.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Regressions from the baseline in {}",
+            regressions.join(", ")
+        )
+        .into())
+    }
+}
+
+fn to_json(baseline: &BTreeMap<String, Measurement>) -> String {
+    let entries: Vec<String> = baseline
+        .iter()
+        .map(|(key, m)| {
+            let statements = match m.statements {
+                Some(n) => format!(", \"statements\": {}", n),
+                None => String::new(),
+            };
+            format!(
+                "  {:?}: {{\"time\": {}, \"allocations\": {}{}}}",
+                key, m.time, m.allocations, statements
+            )
+        })
+        .collect();
+    format!("{{\n{}\n}}\n", entries.join(",\n"))
+}
+
+// just enough JSON for the baseline, which is an object of objects
+// of numbers
+struct JsonParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> JsonParser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            let found = self.rest.chars().next();
+            Err(format!("expected {:?} but found {:?}", c, found))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let end = self.rest.find('"').ok_or("unterminated string")?;
+        let s = &self.rest[..end];
+        if s.contains('\\') {
+            return Err(format!("escapes aren't supported in {:?}", s));
+        }
+        self.rest = &self.rest[end + 1..];
+        Ok(s.to_string())
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(self.rest.len());
+        let n = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        n.parse()
+            .map_err(|_| format!("expected a number but found {:?}", n))
+    }
+
+    // calls f with each key, which has to parse the value
+    fn object(
+        &mut self,
+        mut f: impl FnMut(&mut Self, String) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.expect('{')?;
+        if self.eat('}') {
+            return Ok(());
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(':')?;
+            f(self, key)?;
+            if !self.eat(',') {
+                return self.expect('}');
+            }
+        }
+    }
+}
+
+fn from_json(json: &str) -> Result<BTreeMap<String, Measurement>, String> {
+    let mut parser = JsonParser { rest: json };
+    let mut baseline = BTreeMap::new();
+    parser.object(|parser, key| {
+        let mut m = Measurement::default();
+        parser.object(|parser, field| {
+            let n = parser.number()?;
+            match field.as_str() {
+                "time" => m.time = n,
+                "allocations" => m.allocations = n as u64,
+                "statements" => m.statements = Some(n as u64),
+                _ => return Err(format!("unknown field {:?}", field)),
+            }
+            Ok(())
+        })?;
+        baseline.insert(key, m);
+        Ok(())
+    })?;
+    if !parser.rest.trim().is_empty() {
+        return Err("trailing characters".to_string());
+    }
+    Ok(baseline)
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        COUNTING.store(true, Ordering::Relaxed);
+        let code = "let i = 0\nwhile (i < 10) {\n    print(i)\n    i += 1\n}";
+        let tree_walk = measure(code, Path::new("test.slang"), Backend::TreeWalk).unwrap();
+        // the declaration and the loop, then two statements for each of
+        // the ten iterations
+        assert_eq!(tree_walk.statements, Some(22));
+        assert!(tree_walk.allocations > 0);
+
+        let vm = measure(code, Path::new("test.slang"), Backend::Vm).unwrap();
+        assert_eq!(vm.statements, None);
+        assert_eq!(per_second(vm.statements, vm.time), "n/a");
+    }
+
+    #[test]
+    fn test_dir() {
+        let args = ["--dir".to_string(), "benches".to_string()];
+        assert_eq!(parse_args(&args).unwrap().dir, PathBuf::from("benches"));
+        assert_eq!(parse_args(&[]).unwrap().dir, PathBuf::from("test_files"));
+
+        let missing = std::env::temp_dir().join("slang_no_benchmarks_here");
+        let args = ["--dir".to_string(), missing.to_string_lossy().to_string()];
+        assert!(run(&args).unwrap_err().is::<io::Error>());
+    }
+
+    #[test]
+    fn test_baseline() {
+        let m = Measurement {
+            time: 0.125,
+            allocations: 30,
+            statements: Some(1_000_000),
+        };
+        let mut baseline = BTreeMap::new();
+        baseline.insert("simple_bench/tree-walk".to_string(), m);
+        baseline.insert("fib_arr/vm".to_string(), Measurement::default());
+        assert_eq!(from_json(&to_json(&baseline)), Ok(baseline));
+        assert_eq!(from_json(" { } "), Ok(BTreeMap::new()));
+        assert!(from_json("{\"a\": {\"time\": x}}").is_err());
+        assert!(from_json("{\"a\": {}} }").is_err());
+
+        let slower = Measurement { time: 0.15, ..m };
+        assert_eq!(compare(&slower, &m), ("+20.0% slower".to_string(), true));
+        let noisy = Measurement { time: 0.13, ..m };
+        assert_eq!(compare(&noisy, &m), ("+4.0%".to_string(), false));
+        let allocates = Measurement {
+            allocations: 31,
+            ..m
+        };
+        assert_eq!(
+            compare(&allocates, &m),
+            ("+0.0%, 30 allocations before".to_string(), false)
+        );
+    }
+}
@@ -1,19 +1,39 @@
+use std::fmt;
+use std::rc::Rc;
+
 use crate::{eval::atom::Atom, statement::*};
 
 #[derive(Debug, Clone)]
 pub struct Block {
     pub statements: Vec<Stmt>,
+    /// The line each statement starts on, which is empty for
+    /// blocks that weren't parsed from source
+    pub lines: Vec<usize>,
+    /// The names of the slots in the block's scope, which the
+    /// resolver fills in
+    pub locals: Rc<Vec<String>>,
 }
 
 impl Block {
     pub fn new(statements: Vec<Stmt>) -> Self {
-        Block { statements }
+        Block::with_lines(statements, Vec::new())
     }
 
-    pub fn execute_unscoped(&mut self, state: &mut State) -> Option<Atom> {
+    pub fn with_lines(statements: Vec<Stmt>, lines: Vec<usize>) -> Self {
+        Block {
+            statements,
+            lines,
+            locals: Rc::default(),
+        }
+    }
+
+    pub fn execute_unscoped(&self, state: &mut State) -> Option<Atom> {
         let mut res = None;
 
-        for stmt in self.statements.iter().cloned() {
+        for stmt in &self.statements {
+            if let Some(steps) = &mut state.steps {
+                *steps += 1;
+            }
             res = stmt.execute(state);
             if matches!(res, Some(Atom::Break)) {
                 break;
@@ -23,10 +43,23 @@ impl Block {
         res
     }
 
-    pub fn execute(&mut self, state: &mut State) -> Option<Atom> {
-        state.scopes.push(Scope::default());
+    pub fn execute(&self, state: &mut State) -> Option<Atom> {
+        state.scopes.push(Scope::new(self.locals.clone()));
         let res = self.execute_unscoped(state);
         state.scopes.pop();
         res
     }
 }
+
+/// One statement per line
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", stmt)?;
+        }
+        Ok(())
+    }
+}
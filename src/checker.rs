@@ -0,0 +1,788 @@
+//! A static pass over a parsed [`Block`] that reports errors that are
+//! certain to happen before any of the code runs. Types come from
+//! annotations or are inferred from the values variables are given,
+//! following them through the code in the order it runs. Anything the
+//! checker can't figure out the type of is `Type::Any` and is left for
+//! the runtime checks.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionCall, FunctionData, Local, NativeFunction};
+use crate::module;
+use crate::parser::{Op, S};
+use crate::statement::{Declaration, If, State, Stmt, While};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Float,
+    Str,
+    Bool,
+    Array,
+    Function,
+    Any,
+}
+
+impl Type {
+    /// Parses the names used in annotations, which are the same as
+    /// the names `type_of` gives
+    pub fn from_name(name: &str) -> Option<Type> {
+        let ty = match name {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            "array" => Type::Array,
+            "function" => Type::Function,
+            "any" => Type::Any,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    pub fn of(a: &Atom) -> Type {
+        match a {
+            Atom::Int(_) | Atom::BigInt(_) => Type::Int,
+            Atom::Float(_) => Type::Float,
+            Atom::Str(_) => Type::Str,
+            Atom::Bool(_) => Type::Bool,
+            Atom::Array(_) => Type::Array,
+            Atom::Function(_) | Atom::NativeFunction(_) => Type::Function,
+            // what a module has in it is only known once it's run
+            Atom::Identifier(_)
+            | Atom::Local(_)
+            | Atom::FnCall(_)
+            | Atom::Break
+            | Atom::Module(_) => Type::Any,
+        }
+    }
+
+    /// Whether a runtime value fits this type
+    pub fn matches(self, a: &Atom) -> bool {
+        self == Type::Any || self == Type::of(a)
+    }
+
+    // two types conflict only if both are known
+    fn conflicts(self, other: Type) -> bool {
+        self != Type::Any && other != Type::Any && self != other
+    }
+
+    fn is_number(self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    // the types a value of this type could actually have
+    fn possible(self) -> Vec<Type> {
+        match self {
+            Type::Any => vec![
+                Type::Int,
+                Type::Float,
+                Type::Str,
+                Type::Bool,
+                Type::Array,
+                Type::Function,
+            ],
+            ty => vec![ty],
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Str => "str",
+            Type::Bool => "bool",
+            Type::Array => "array",
+            Type::Function => "function",
+            Type::Any => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on line {}", self.msg, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Signature {
+    arg_types: Vec<Option<Type>>,
+    /// the annotated return type, or the one inferred from the body
+    return_type: Option<Type>,
+}
+
+impl Signature {
+    fn new(f: &FunctionData) -> Self {
+        Signature {
+            arg_types: f.arg_types.clone(),
+            return_type: f.return_type,
+        }
+    }
+
+    fn native(f: &NativeFunction) -> Option<Self> {
+        f.arity.map(|arity| Signature {
+            arg_types: vec![None; arity],
+            return_type: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Var {
+    ty: Type,
+    annotation: Option<Type>,
+    sig: Option<Signature>,
+}
+
+impl Var {
+    fn new(ty: Type, annotation: Option<Type>) -> Self {
+        Var {
+            ty,
+            annotation,
+            sig: None,
+        }
+    }
+}
+
+type Scope = HashMap<String, Var>;
+
+/// Checks code that is about to run in `state`, so variables that
+/// already exist there are known to the checker.
+pub fn check(block: &Block, state: &State) -> Result<(), Vec<TypeError>> {
+    let mut checker = Checker::new(state);
+    let mut declared = state
+        .scopes
+        .iter()
+        .flat_map(|scope| scope.vars.keys().cloned())
+        .collect();
+    find_reassigned(block, &mut declared, &mut checker.reassigned);
+    checker.check_statements(block);
+
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/// The type `expr` would have if it ran in `state`, without
+/// running it
+pub fn expr_type(expr: &S, state: &State) -> Result<Type, Vec<TypeError>> {
+    let mut checker = Checker::new(state);
+    let ty = checker.expr_type(expr);
+
+    if checker.errors.is_empty() {
+        Ok(ty)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct Checker {
+    scopes: Vec<Scope>,
+    errors: Vec<TypeError>,
+    line: usize,
+    /// Index of the first scope of the function being checked.
+    /// Variables from further out depend on where the function is
+    /// called from, so only their signatures are trusted.
+    fn_scope: usize,
+    /// How many loops of the function being checked the statement is
+    /// in. A break never leaves a function.
+    loops: usize,
+    /// Names that are given another value somewhere in the code. A
+    /// function body can run after that's happened, so it doesn't trust
+    /// the signatures they had from further out.
+    reassigned: HashSet<String>,
+}
+
+impl Checker {
+    fn new(state: &State) -> Self {
+        let scopes = state
+            .scopes
+            .iter()
+            .map(|scope| {
+                scope
+                    .vars
+                    .iter()
+                    .map(|(name, val)| {
+                        let sig = match val {
+                            Atom::Function(f) => Some(Signature::new(f)),
+                            Atom::NativeFunction(f) => Signature::native(f),
+                            _ => None,
+                        };
+                        let var = Var {
+                            ty: Type::of(val),
+                            annotation: None,
+                            sig,
+                        };
+                        (name.clone(), var)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Checker {
+            scopes,
+            errors: Vec::new(),
+            line: 0,
+            fn_scope: 0,
+            loops: 0,
+            reassigned: HashSet::new(),
+        }
+    }
+
+    fn error(&mut self, msg: String) {
+        self.errors.push(TypeError {
+            line: self.line,
+            msg,
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Var> {
+        let (i, var) = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| scope.get(name).map(|var| (i, var)))?;
+
+        if i < self.fn_scope {
+            let sig = if self.reassigned.contains(name) {
+                None
+            } else {
+                var.sig.clone()
+            };
+            Some(Var {
+                sig,
+                ..Var::new(Type::Any, None)
+            })
+        } else {
+            Some(var.clone())
+        }
+    }
+
+    fn declare(&mut self, name: &str, var: Var) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), var);
+    }
+
+    // after an assignment succeeds the variable is known to have
+    // the assigned type, and the signature of the function it was
+    // given, if it was given one. a function assigning to a variable
+    // from further out changes it whenever it's called, so only the
+    // signature is forgotten
+    fn refine(&mut self, name: &str, ty: Type, sig: Option<Signature>) {
+        let fn_scope = self.fn_scope;
+        let found = self
+            .scopes
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| scope.get_mut(name).map(|var| (i, var)));
+        match found {
+            Some((i, var)) if i >= fn_scope => {
+                if var.ty == Type::Any {
+                    var.ty = ty;
+                }
+                var.sig = sig;
+            }
+            Some((_, var)) => var.sig = None,
+            None => {}
+        }
+    }
+
+    // joins the variables from two paths through the code, so only
+    // types both paths agree on are kept
+    fn merge(&mut self, other: Vec<Scope>) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, var) in scope.iter_mut() {
+                let other = other.get(name);
+                if other.map(|v| v.ty) != Some(var.ty) {
+                    var.ty = Type::Any;
+                }
+                if other.map(|v| &v.sig) != Some(&var.sig) {
+                    var.sig = None;
+                }
+            }
+        }
+    }
+
+    fn check_block(&mut self, block: &Block) -> Type {
+        self.scopes.push(Scope::new());
+        let ty = self.check_statements(block);
+        self.scopes.pop();
+        ty
+    }
+
+    // gives the type of the value the block evaluates to
+    fn check_statements(&mut self, block: &Block) -> Type {
+        let mut res = Type::Any;
+        for (i, stmt) in block.statements.iter().enumerate() {
+            if let Some(&line) = block.lines.get(i) {
+                self.line = line;
+            }
+            res = self.check_stmt(stmt);
+        }
+        res
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) -> Type {
+        match stmt {
+            Stmt::ExprStmt(expr) => self.expr_type(expr),
+            Stmt::PrintStmt(expr) => {
+                self.expr_type(expr);
+                Type::Any
+            }
+            Stmt::Dec(dec) => {
+                self.check_declaration(dec);
+                Type::Any
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                self.expr_type(cond);
+                let before = self.scopes.clone();
+                let then_ty = self.check_block(then_block);
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                let else_ty = self.check_block(else_block);
+                self.merge(after_then);
+
+                if then_ty == else_ty {
+                    then_ty
+                } else {
+                    Type::Any
+                }
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                // the loop might not run at all
+                self.expr_type(cond);
+                let before = self.scopes.clone();
+                self.loops += 1;
+                self.check_block(loop_block);
+                self.loops -= 1;
+                self.merge(before);
+                Type::Any
+            }
+            Stmt::Block(block) => self.check_block(block),
+            Stmt::Break => {
+                if self.loops == 0 {
+                    self.error("break outside of a loop".to_string());
+                }
+                Type::Any
+            }
+            Stmt::Import(import) => {
+                let name = match &import.name {
+                    Some(name) => Some(name.clone()),
+                    None => module::default_name(Path::new(&import.path)),
+                };
+                if let Some(name) = name {
+                    self.declare(&name, Var::new(Type::Any, None));
+                }
+                Type::Any
+            }
+        }
+    }
+
+    fn check_declaration(&mut self, dec: &Declaration) {
+        if let (S::Atom(Atom::Function(f)), true) = (&dec.rhs, dec.alias) {
+            return self.check_function(&dec.lhs, f);
+        }
+
+        let rhs = self.expr_type(&dec.rhs);
+        if dec.alias {
+            if let Some(ann) = dec.ty {
+                if rhs.conflicts(ann) {
+                    self.error(format!(
+                        "Cannot assign {} to {}, which is declared as {}",
+                        rhs, dec.lhs, ann
+                    ));
+                }
+            }
+
+            let var = Var {
+                sig: self.fn_sig(&dec.rhs),
+                ..Var::new(dec.ty.unwrap_or(rhs), dec.ty)
+            };
+            self.declare(&dec.lhs, var);
+        } else if let Some(var) = self.lookup(&dec.lhs) {
+            // State::declare only allows assigning the same type
+            let (expected, declared) = match var.annotation {
+                Some(ann) => (ann, "declared as "),
+                None => (var.ty, ""),
+            };
+            if rhs.conflicts(expected) {
+                self.error(format!(
+                    "Cannot assign {} to {}, which is {}{}",
+                    rhs, dec.lhs, declared, expected
+                ));
+            }
+
+            match dec.plus_or_minus {
+                Some(true) => self.op_type(&Op::Plus, &[var.ty, rhs]),
+                Some(false) => self.op_type(&Op::Minus, &[var.ty, rhs]),
+                None => rhs,
+            };
+            let sig = match dec.plus_or_minus {
+                Some(_) => None,
+                None => self.fn_sig(&dec.rhs),
+            };
+            self.refine(&dec.lhs, rhs, sig);
+        }
+    }
+
+    // functions passed around by name keep their signature
+    fn fn_sig(&self, expr: &S) -> Option<Signature> {
+        match expr {
+            S::Atom(Atom::Identifier(name)) | S::Atom(Atom::Local(Local { name, .. })) => {
+                self.lookup(name)?.sig
+            }
+            _ => None,
+        }
+    }
+
+    fn check_function(&mut self, name: &str, f: &FunctionData) {
+        // declared first so recursive calls are checked
+        let var = Var {
+            sig: Some(Signature::new(f)),
+            ..Var::new(Type::Function, None)
+        };
+        self.declare(name, var);
+
+        let line = self.line;
+        let outer_fn_scope = self.fn_scope;
+        let outer_loops = self.loops;
+        self.fn_scope = self.scopes.len();
+        self.loops = 0;
+
+        let args = f
+            .arg_names
+            .iter()
+            .zip(&f.arg_types)
+            .map(|(arg, &ann)| (arg.clone(), Var::new(ann.unwrap_or(Type::Any), ann)));
+        self.scopes.push(args.collect());
+        let returned = self.check_statements(&f.fn_block);
+        self.scopes.pop();
+
+        self.fn_scope = outer_fn_scope;
+        self.loops = outer_loops;
+        self.line = line;
+
+        match f.return_type {
+            Some(ret) if returned.conflicts(ret) => self.error(format!(
+                "Function {} returns {} but is declared to return {}",
+                name, returned, ret
+            )),
+            Some(_) => {}
+            None => {
+                let var = self.scopes.last_mut().unwrap().get_mut(name).unwrap();
+                if let Some(sig) = &mut var.sig {
+                    sig.return_type = Some(returned);
+                }
+            }
+        }
+    }
+
+    fn expr_type(&mut self, expr: &S) -> Type {
+        match expr {
+            S::Atom(Atom::Identifier(name)) | S::Atom(Atom::Local(Local { name, .. })) => {
+                self.lookup(name).map_or(Type::Any, |v| v.ty)
+            }
+            S::Atom(Atom::FnCall(call)) => self.call_type(call),
+            S::Atom(Atom::Array(xs)) => {
+                xs.iter().for_each(|x| {
+                    self.expr_type(x);
+                });
+                Type::Array
+            }
+            S::Atom(a) => Type::of(a),
+            S::Cons(Op::Access, xs) => {
+                // the right side is a name rather than an expression
+                self.expr_type(&xs[0]);
+                Type::Any
+            }
+            S::Cons(op, xs) => {
+                let tys: Vec<Type> = xs.iter().map(|x| self.expr_type(x)).collect();
+                self.op_type(op, &tys)
+            }
+        }
+    }
+
+    fn call_type(&mut self, call: &FunctionCall) -> Type {
+        let arg_tys: Vec<Type> = call.args.iter().map(|a| self.expr_type(a)).collect();
+        let sig = match self.lookup(&call.name) {
+            Some(Var { sig: Some(sig), .. }) => sig,
+            Some(Var { ty, .. }) if ty.conflicts(Type::Function) => {
+                self.error(format!("{} is {}, not a function", call.name, ty));
+                return Type::Any;
+            }
+            _ => return Type::Any,
+        };
+
+        if sig.arg_types.len() != arg_tys.len() {
+            self.error(format!(
+                "Function {} takes {} arguments but {} were given",
+                call.name,
+                sig.arg_types.len(),
+                arg_tys.len()
+            ));
+        }
+        for (i, (ann, ty)) in sig.arg_types.iter().zip(arg_tys).enumerate() {
+            if let Some(ann) = ann {
+                if ty.conflicts(*ann) {
+                    self.error(format!(
+                        "Argument {} of {} should be {} but is {}",
+                        i + 1,
+                        call.name,
+                        ann,
+                        ty
+                    ));
+                }
+            }
+        }
+
+        sig.return_type.unwrap_or(Type::Any)
+    }
+
+    // tries every type the operands could have, so an error is only
+    // reported if the operator fails for all of them
+    fn op_type(&mut self, op: &Op, tys: &[Type]) -> Type {
+        let combos = tys.iter().fold(vec![vec![]], |combos, ty| {
+            combos
+                .iter()
+                .flat_map(|combo: &Vec<Type>| {
+                    ty.possible().into_iter().map(move |t| {
+                        let mut combo = combo.clone();
+                        combo.push(t);
+                        combo
+                    })
+                })
+                .collect()
+        });
+
+        let results: Vec<Type> = combos.iter().filter_map(|c| op_type(op, c)).collect();
+        match results.first() {
+            None => {
+                let operands: Vec<String> = tys.iter().map(|t| t.to_string()).collect();
+                self.error(format!("Can't use {} with {}", op, operands.join(" and ")));
+                Type::Any
+            }
+            Some(&ty) if results.iter().all(|&t| t == ty) => ty,
+            Some(_) => Type::Any,
+        }
+    }
+}
+
+// adds the names that are assigned to, or declared when they already
+// were, anywhere in the block, including in the functions it declares
+fn find_reassigned(block: &Block, declared: &mut HashSet<String>, names: &mut HashSet<String>) {
+    for stmt in &block.statements {
+        match stmt {
+            Stmt::Dec(dec) => {
+                if !dec.alias || !declared.insert(dec.lhs.clone()) {
+                    names.insert(dec.lhs.clone());
+                }
+                if let S::Atom(Atom::Function(f)) = &dec.rhs {
+                    find_reassigned(&f.fn_block, declared, names);
+                }
+            }
+            Stmt::IfStmt(If {
+                then_block,
+                else_block,
+                ..
+            }) => {
+                find_reassigned(then_block, declared, names);
+                find_reassigned(else_block, declared, names);
+            }
+            Stmt::WhileStmt(While { loop_block, .. }) => {
+                find_reassigned(loop_block, declared, names)
+            }
+            Stmt::Block(block) => find_reassigned(block, declared, names),
+            Stmt::ExprStmt(_) | Stmt::PrintStmt(_) | Stmt::Break | Stmt::Import(_) => {}
+        }
+    }
+}
+
+// the type an operator gives for the types of its operands, following
+// the rules in atom.rs, or None if it always fails for them
+fn op_type(op: &Op, tys: &[Type]) -> Option<Type> {
+    use Type::*;
+
+    let ty = match (op, tys) {
+        (Op::Plus, [Str, Str]) => Str,
+        (Op::Plus, [Str, a]) | (Op::Plus, [a, Str]) if a.is_number() => Str,
+        (Op::Minus | Op::Negate, [a]) if a.is_number() || *a == Bool => *a,
+        (Op::Plus | Op::Minus | Op::Multiply | Op::FloorDivide | Op::Mod, [Int, Int]) => Int,
+        (Op::Multiply, [Str, Int]) | (Op::Multiply, [Int, Str]) => Str,
+        (Op::Power, [Int, Int]) => Any, // negative powers give a Float
+        (
+            Op::Plus
+            | Op::Minus
+            | Op::Multiply
+            | Op::Divide
+            | Op::FloorDivide
+            | Op::Mod
+            | Op::Power,
+            [a, b],
+        ) if a.is_number() && b.is_number() => Float,
+        (Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight, [Int, Int]) => Int,
+        (Op::Equal | Op::NotEqual, _) => Bool,
+        (Op::Less | Op::Greater, [Str, Str]) => Bool,
+        (Op::Less | Op::Greater, [a, b]) if a.is_number() && b.is_number() => Bool,
+        (Op::And | Op::Or, [Bool, Bool]) => Bool,
+        (Op::Indexing, [Array, Int]) => Any,
+        (Op::Indexing, [Str, Int]) => Str,
+        (Op::Slice, [a, rest @ ..])
+            if (*a == Str || *a == Array) && rest.iter().all(|&t| t == Int) =>
+        {
+            *a
+        }
+        (Op::Format(_), _) => Str,
+        (Op::Access | Op::PlusAssign | Op::MinusAssign, _) => Any,
+        _ => return None,
+    };
+    Some(ty)
+}
+
+#[cfg(test)]
+mod checker_tests {
+    use super::*;
+    use crate::{parse_block, scan_tokens, Lexer};
+
+    fn errors(code: &str) -> Vec<String> {
+        let block = parse_block(&mut Lexer::new(scan_tokens(code)));
+        match check(&block, &State::default()) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_annotations() {
+        let ok = "let x: int = 5\nlet y: float = x / 2\nfn add(a: int, b: int) -> int {\n a + b\n}\nx = add(x, 1)";
+        assert_eq!(errors(ok), Vec::<String>::new());
+
+        assert_eq!(
+            errors("let a = 1\n\nlet x: int = \"a\""),
+            vec!["Cannot assign str to x, which is declared as int on line 3"]
+        );
+        assert_eq!(
+            errors("let x: str = \"a\"\nif (true) {\n x = 1.5\n}"),
+            vec!["Cannot assign float to x, which is declared as str on line 3"]
+        );
+        assert_eq!(
+            errors("fn f(a: int) -> str {\n a * 2\n}\nf(\"a\")\nf(1, 2)"),
+            vec![
+                "Function f returns int but is declared to return str on line 1",
+                "Argument 1 of f should be int but is str on line 4",
+                "Function f takes 1 arguments but 2 were given on line 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_types_pass() {
+        // the checker can't know what these are, so they're left to
+        // the runtime checks
+        assert_eq!(
+            errors("fn f(a) {\n a\n}\nlet x: int = f(\"a\")"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("let a = [1]\nlet x: str = a[0]"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_inference() {
+        assert_eq!(
+            errors("let a = [1, 2]\nlet s = \"x\"\nlet b = s + a"),
+            vec!["Can't use + with str and array on line 3"]
+        );
+        assert_eq!(
+            errors("let x = 10\nx = \"a string\""),
+            vec!["Cannot assign str to x, which is int on line 2"]
+        );
+        assert_eq!(
+            errors("let n = 5\nlet m = n * 2\nm[0]"),
+            vec!["Can't use [] with int and int on line 3"]
+        );
+        assert_eq!(
+            errors("fn name() {\n \"slang\"\n}\nlet s = name()\ns - 1"),
+            vec!["Can't use - with str and int on line 5"]
+        );
+        assert_eq!(
+            errors("let f = 1.5\nf(2)\nlen([1], 2)"),
+            vec![
+                "f is float, not a function on line 2",
+                "Function len takes 1 arguments but 2 were given on line 3",
+            ]
+        );
+
+        // an array plus anything is an error, even if the other
+        // side's type isn't known
+        assert_eq!(
+            errors("fn f(x) {\n [x] + x\n}"),
+            vec!["Can't use + with array and any on line 2"]
+        );
+    }
+
+    #[test]
+    fn test_flow() {
+        // x is only known to be an int on one path
+        let branches = "let x = [1]\nif (true) {\n let x = 1\n}\nx[0]";
+        assert_eq!(errors(branches), Vec::<String>::new());
+
+        let merged =
+            "fn f(a) {\n a\n}\nlet x = f(1)\nif (true) {\n x = 1\n} else {\n x = 2\n}\nx[0]";
+        assert_eq!(
+            errors(merged),
+            vec!["Can't use [] with int and int on line 10"]
+        );
+
+        let maybe = "fn f(a) {\n a\n}\nlet x = f(1)\nwhile (false) {\n x = 1\n}\nx[0]";
+        assert_eq!(errors(maybe), Vec::<String>::new());
+
+        // a function sees its caller's variables, so outer variables
+        // aren't trusted inside it
+        let outer = "let y = 1\nfn f() {\n y[0]\n}";
+        assert_eq!(errors(outer), Vec::<String>::new());
+
+        // a function assigned to a variable replaces its signature
+        let assigned = "fn a(x) {\n x\n}\nfn b(x, y) {\n x + y\n}\nlet g = a\ng = b\ng(1, 2)";
+        assert_eq!(errors(assigned), Vec::<String>::new());
+        let in_fn = "fn a(x) {\n x\n}\nfn b(x, y) {\n x + y\n}\nlet g = a\nfn h() {\n g = b\n 0\n}\nh()\ng(1, 2)";
+        assert_eq!(errors(in_fn), Vec::<String>::new());
+
+        // f is redeclared before g runs, so g can't trust what f returns
+        let redeclared = "fn f() {\n 1\n}\nfn g() {\n f()[0]\n}\nfn f() {\n [5]\n}\ng()";
+        assert_eq!(errors(redeclared), Vec::<String>::new());
+        let stable = "fn f() {\n 1\n}\nfn g() {\n f()[0]\n}";
+        assert_eq!(
+            errors(stable),
+            vec!["Can't use [] with int and int on line 5"]
+        );
+
+        // a break in a function can't stop the loop it's called from
+        let in_fn = "fn stop() {\n break\n}\nwhile (true) {\n if (true) {\n  break\n }\n stop()\n}";
+        assert_eq!(errors(in_fn), vec!["break outside of a loop on line 2"]);
+        assert_eq!(errors("break"), vec!["break outside of a loop on line 1"]);
+    }
+}
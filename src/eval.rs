@@ -4,7 +4,12 @@ use crate::State;
 pub mod atom;
 use atom::Atom;
 
+mod convert;
 mod function;
+pub use function::{
+    call_function, call_native, call_value, check_return, enter_function, install_builtins,
+    Exit,
+};
 
 pub fn eval_expr(expr: &S, state: &mut State) -> Atom {
     let mut eval = |expr: &S| eval_expr(expr, state);
@@ -13,10 +18,17 @@ pub fn eval_expr(expr: &S, state: &mut State) -> Atom {
             Atom::Identifier(name) => {
                 match state.get_variable(name) {
                     Some(a) => a.clone(),
-                    None => panic!("Variable {} undefined in state {:?}", name, state),
+                    None => panic!("Variable {} is undefined", name),
                 }
             }
-            Atom::FnCall(f) => function::eval_function_call(f, state).unwrap(),
+            Atom::Local(local) => match state.get_local(local.slot) {
+                Some(a) => a.clone(),
+                None => panic!("Variable {} is undefined", local.name),
+            },
+            Atom::FnCall(f) => match function::eval_function_call(f, state) {
+                Some(a) => a,
+                None => panic!("Function {} didn't return a value", f.name),
+            },
             Atom::Array(arr) => {
                 let new_arr = arr.iter().map(|s| S::Atom(eval(s))).collect();
                 Atom::Array(new_arr).clone()
@@ -26,24 +38,37 @@ pub fn eval_expr(expr: &S, state: &mut State) -> Atom {
         S::Cons(op, xs) => {
             let slice = xs.as_slice();
             match (op, slice) {
-                (Op::Plus, [a, b, ..]) => eval(&a) + eval(&b),
-                (Op::Minus, [a, b, ..]) => eval(&a) - eval(&b),
-                (Op::Minus, [a]) => eval(&a).negate(),
-                (Op::Multiply, [a, b, ..]) => eval(&a) * eval(&b),
-                (Op::Divide, [a, b, ..]) => eval(&a) / eval(&b),
-                (Op::Negate, [a]) => eval(&a).negate(),
-                (Op::Equal, [a, b]) => Atom::Bool(eval(&a) == (eval(&b))),
-                (Op::NotEqual, [a, b]) => Atom::Bool(eval(&a) != (eval(&b))),
-                (Op::Less, [a, b]) => Atom::Bool(eval(&a) < (eval(&b))),
-                (Op::Greater, [a, b]) => Atom::Bool(eval(&a) > (eval(&b))),
-                (Op::Mod, [a, b]) => eval(&a).modulus(&eval(&b)),
-                (Op::And, [a, b]) => eval(&a).and(&eval(&b)),
-                (Op::Or, [a, b]) => eval(&a).or(&eval(&b)),
+                (Op::Plus, [a, b, ..]) => eval(a) + eval(b),
+                (Op::Minus, [a, b, ..]) => eval(a) - eval(b),
+                (Op::Minus, [a]) => eval(a).negate(),
+                (Op::Multiply, [a, b, ..]) => eval(a) * eval(b),
+                (Op::Divide, [a, b, ..]) => eval(a) / eval(b),
+                (Op::FloorDivide, [a, b]) => eval(a).floor_div(&eval(b)),
+                (Op::Power, [a, b]) => eval(a).pow(&eval(b)),
+                (Op::BitAnd, [a, b]) => eval(a) & eval(b),
+                (Op::BitOr, [a, b]) => eval(a) | eval(b),
+                (Op::BitXor, [a, b]) => eval(a) ^ eval(b),
+                (Op::ShiftLeft, [a, b]) => eval(a) << eval(b),
+                (Op::ShiftRight, [a, b]) => eval(a) >> eval(b),
+                (Op::Negate, [a]) => eval(a).negate(),
+                (Op::Equal, [a, b]) => Atom::Bool(eval(a) == (eval(b))),
+                (Op::NotEqual, [a, b]) => Atom::Bool(eval(a) != (eval(b))),
+                (Op::Less, [a, b]) => Atom::Bool(eval(a) < (eval(b))),
+                (Op::Greater, [a, b]) => Atom::Bool(eval(a) > (eval(b))),
+                (Op::Mod, [a, b]) => eval(a).modulus(&eval(b)),
+                (Op::And, [a, b]) => eval(a).and(&eval(b)),
+                (Op::Or, [a, b]) => eval(a).or(&eval(b)),
                 (Op::Indexing, [a, b]) => {
-                    let a = eval(&a).index(&eval(&b));
+                    let a = eval(a).index(&eval(b));
                     eval(&S::Atom(a))
                 },
-                (Op::Access, [a, b]) => eval(&a).access(&b),
+                (Op::Slice, [a, start]) => eval(a).slice(&eval(start), None),
+                (Op::Slice, [a, start, end]) => eval(a).slice(&eval(start), Some(&eval(end))),
+                (Op::Access, [a, b]) => match eval(a) {
+                    Atom::Module(m) => m.access(b, state),
+                    a => a.access(b),
+                },
+                (Op::Format(spec), [a]) => Atom::Str(spec.format(&eval(a))),
                 _ => panic!("invalid expr: {}", expr),
             }
         }
@@ -66,6 +91,30 @@ mod eval_tests {
         }
     }
 
+    // runs the setup code, then checks that each input gives the same
+    // value as its expected code, with both backends. values are
+    // compared by their Debug output, so that 4 and 4.0 aren't the same
+    macro_rules! eval_cases {
+        ( $setup:expr; $( $input:expr => $expected:expr ),* $(,)? ) => {
+            for backend in [crate::Backend::TreeWalk, crate::Backend::Vm] {
+                let mut interp = crate::Interpreter::new();
+                interp.set_backend(backend);
+                interp.eval_str($setup).unwrap();
+                $(
+                    let res = interp.eval_str($input).unwrap();
+                    let expected = interp.eval_str($expected).unwrap();
+                    assert_eq!(
+                        format!("{:?}", res),
+                        format!("{:?}", expected),
+                        "{} with {:?}",
+                        $input,
+                        backend
+                    );
+                )*
+            }
+        }
+    }
+
     #[test]
     fn test_eval() {
         eval_test!(
@@ -77,7 +126,304 @@ mod eval_tests {
             "3 * (4 + 5 * 8)" => Atom::Int(3 * (4 + 5 * 8)),
             "4.4 * (9 * 5 - 8 /     (3 - 4))" => Atom::Float(4.4 * (9.0 * 5.0 - 8.0 / (3.0 - 4.0))),
             "3.25/4 * 5" => Atom::Float(3.25 / 4.0 * 5.0),
-            "(4.0 * 12.5) + 6.0 / (12.5 + 3.0)" => Atom::Float((4.0 * 12.5) + 6.0 / (12.5 + 3.0))
+            "(4.0 * 12.5) + 6.0 / (12.5 + 3.0)" => Atom::Float((4.0 * 12.5) + 6.0 / (12.5 + 3.0)),
+            "6 / 3" => Atom::Float(2.0),
+            "7 // 2" => Atom::Int(3),
+            "-7 // 2" => Atom::Int(-4),
+            "7.5 // 2" => Atom::Float(3.0),
+            "-7 % 3" => Atom::Int(2),
+            "7 % -3" => Atom::Int(-2),
+            "5.5 % 2" => Atom::Float(1.5),
+            "-1 % 2.5" => Atom::Float(1.5),
+            "2 ** 10" => Atom::Int(1024),
+            "2 ** -1" => Atom::Float(0.5),
+            "-2 ** 2" => Atom::Int(-4),
+            "2 ** 3 ** 2" => Atom::Int(512),
+            "4 ** 0.5" => Atom::Float(2.0),
+            "12 & 10" => Atom::Int(8),
+            "12 | 10" => Atom::Int(14),
+            "12 ^ 10" => Atom::Int(6),
+            "1 << 4" => Atom::Int(16),
+            "-16 >> 2" => Atom::Int(-4),
+            "1 << 64 >> 64" => Atom::Int(1),
+            "10 / 2 == 5" => Atom::Bool(true),
+            "sqrt(16) == 4" => Atom::Bool(true),
+            "0.5 == 0" => Atom::Bool(false),
+            "3 != 3.0" => Atom::Bool(false),
+            "100000000000000000000 == 100000000000000000000.0" => Atom::Bool(true),
+            "9007199254740993 == 9007199254740992.0" => Atom::Bool(false),
+            "[1, 2.0] == [1.0, 2]" => Atom::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_higher_order() {
+        eval_cases!(
+            "\
+fn square(x) {
+    x * x
+}
+fn is_even(x) {
+    x % 2 == 0
+}
+fn add(acc, x) {
+    acc + x
+}
+fn neg(x) {
+    -x
+}
+fn big(x) {
+    x > 10
+}
+let nums = [5, 3, 8, 1, 4]
+let squares = map(nums, square)";
+            "squares" => "[25, 9, 64, 1, 16]",
+            "filter(squares, is_even)" => "[64, 16]",
+            "reduce(squares, 0, add)" => "115",
+            "sort(nums)" => "[1, 3, 4, 5, 8]",
+            "sort_by(nums, neg)" => "[8, 5, 4, 3, 1]",
+            "any(nums, big)" => "false",
+            "all(squares, big)" => "false",
+            "find(squares, big)" => "0",
+            "find(nums, big)" => "-1",
+            "zip(nums, [\"a\", \"b\"])" => "[[5, \"a\"], [3, \"b\"]]",
+            "enumerate([\"x\", \"y\"])" => "[[0, \"x\"], [1, \"y\"]]",
         );
     }
+
+    #[test]
+    fn test_interpolation() {
+        eval_cases!(
+            "\
+let x = 3.14159
+let n = -42
+let name = \"pong\"
+fn double(a) {
+    a * 2
+}";
+            "\"x={x:.2}\"" => "\"x=3.14\"",
+            "\"n={n:05}\"" => "\"n=-0042\"",
+            "\"[{name:6}]\"" => "\"[pong  ]\"",
+            "\"[{n:5}]\"" => "\"[  -42]\"",
+            "\"{{braces}}\"" => "r\"{braces}\"",
+            "\"{double(n) + 1}\"" => "\"-83\"",
+            "\"{\"nested\"}\"" => "\"nested\"",
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        eval_cases!(
+            "let t = trim(\"  Hello, World  \")";
+            "t" => "\"Hello, World\"",
+            "len(t)" => "12",
+            "t[0]" => "\"H\"",
+            "t[7:]" => "\"World\"",
+            "t[:5]" => "\"Hello\"",
+            "t[1:4]" => "\"ell\"",
+            "upper(t)" => "\"HELLO, WORLD\"",
+            "lower(t)" => "\"hello, world\"",
+            "split(t, \", \")" => "[\"Hello\", \"World\"]",
+            "split(\"abc\", \"\")" => "[\"a\", \"b\", \"c\"]",
+            "join([\"a\", 1, 2.5], \"-\")" => "\"a-1-2.5\"",
+            "contains(t, \"lo, W\")" => "true",
+            "contains(t, \"xyz\")" => "false",
+            "contains([1, \"a\"], \"a\")" => "true",
+            "replace(t, \"l\", \"L\")" => "\"HeLLo, WorLd\"",
+            "starts_with(t, \"Hell\")" => "true",
+            "ends_with(t, \"ld\")" => "true",
+            "find(t, \"World\")" => "7",
+            "find(\"héllo\", \"l\")" => "2",
+            "find(t, \"xyz\")" => "-1",
+            "chars(\"hé\")" => "[\"h\", \"é\"]",
+            "ord(\"a\")" => "97",
+            "chr(9731)" => "\"\\u{2603}\"",
+            "\"ab\" * 3" => "\"ababab\"",
+            "2 * \"-\"" => "\"--\"",
+            "\"apple\" < \"banana\"" => "true",
+            "\"b\" > \"abc\"" => "true",
+            "sort([\"pear\", \"apple\", \"fig\"])" => "[\"apple\", \"fig\", \"pear\"]",
+            "[1, 2, 3, 4][1:3]" => "[2, 3]",
+            "\"{t[:5]}!\"" => "\"Hello!\"",
+        );
+    }
+
+    #[test]
+    fn test_bignum() {
+        eval_cases!(
+            "\
+fn factorial(n) {
+    if (n < 2) {
+        1
+    } else {
+        n * factorial(n - 1)
+    }
+}
+let f = factorial(30)
+let big = 9223372036854775807
+big += 1
+let n = 100000000000000000000";
+            "\"{f}\"" => "\"265252859812191058636308480000000\"",
+            "f // factorial(28)" => "870",
+            "f > 9223372036854775807" => "true",
+            "f - f" => "0",
+            "\"{big}\"" => "\"9223372036854775808\"",
+            "big - 2" => "9223372036854775806",
+            "n % 7" => "2",
+            "-n < 0" => "true",
+            "n * 1.5" => "150000000000000000000.0",
+            "round(100000000000000000000.0) == n" => "true",
+            "\"x\" + n" => "\"x100000000000000000000\"",
+        );
+    }
+
+    #[test]
+    fn test_math() {
+        eval_cases!(
+            "\
+fn close(a, b) {
+    abs(a - b) < 0.000001
+}";
+            "sqrt(16)" => "4.0",
+            "pow(2, 10)" => "1024",
+            "abs(-3)" => "3",
+            "abs(-2.5)" => "2.5",
+            "ceil(1.2)" => "2",
+            "floor(-1.5)" => "-2",
+            "round(2.5)" => "3",
+            "min(3, 1, 2)" => "1",
+            "max([3, 1, 2])" => "3",
+            "max(1, 2.5)" => "2.5",
+            "close(sin(PI / 2), 1)" => "true",
+            "close(cos(0), 1)" => "true",
+            "close(tan(PI / 4), 1)" => "true",
+            "close(atan2(1, 1), PI / 4)" => "true",
+            "close(log(E), 1)" => "true",
+            "close(exp(1), E)" => "true",
+            "int(3.9)" => "3",
+            "int(-3.9)" => "-3",
+            "int(\" 42 \")" => "42",
+            "float(2)" => "2.0",
+            "float(\"1.5\")" => "1.5",
+            "str(12)" => "\"12\"",
+            "str(1.5)" => "\"1.5\"",
+        );
+    }
+
+    #[test]
+    fn test_random() {
+        eval_cases!(
+            "\
+seed(42)
+let a = random()
+let b = random_int(1, 6)
+seed(42)
+let in_range = true
+for (let i = 0; i < 100; i += 1) {
+    let r = random()
+    let d = random_int(-2, 2)
+    in_range = in_range && !(r < 0.0) && (r < 1.0) && (d > -3) && (d < 3)
+}";
+            "seed(42)\nrandom() == a" => "true",
+            "random_int(1, 6) == b" => "true",
+            "in_range" => "true",
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        eval_cases!(
+            "\
+fn f(x) {
+    x
+}";
+            "type_of(1)" => "\"int\"",
+            "type_of(100000000000000000000)" => "\"int\"",
+            "type_of(1.5)" => "\"float\"",
+            "type_of(\"a\")" => "\"str\"",
+            "type_of(true)" => "\"bool\"",
+            "type_of([1])" => "\"array\"",
+            "type_of(f)" => "\"function\"",
+            "type_of(len)" => "\"function\"",
+            "is_int(3)" => "true",
+            "is_int(3.0)" => "false",
+            "is_float(3.0)" => "true",
+            "is_str(\"3\")" => "true",
+            "is_bool(false)" => "true",
+            "is_array([])" => "true",
+            "is_function(map)" => "true",
+            "bool(0)" => "false",
+            "bool(2.5)" => "true",
+            "bool(\"false\")" => "false",
+            "int(true)" => "1",
+            "float(false)" => "0.0",
+            "str([1, 2])" => "\"[1, 2]\"",
+            "str([\"a\", [1.5]])" => "\"[\\\"a\\\", [1.5]]\"",
+            "int(7 / 2)" => "3",
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut interp = crate::Interpreter::new();
+        for code in ["1 / 0", "1 // 0", "1 % 0", "1.5 / 0.0", "1 % 0.0"] {
+            let err = interp.eval_str(code).unwrap_err();
+            assert_eq!(err.to_string(), "Division by zero");
+        }
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let mut interp = crate::Interpreter::new();
+        let cases = [
+            ("int(\"abc\")", "Can't convert \"abc\" to an int"),
+            ("float([1])", "Can't convert array [1] to a float"),
+            ("bool(\"yes\")", "Can't convert \"yes\" to a bool"),
+        ];
+        for (code, msg) in cases {
+            assert_eq!(interp.eval_str(code).unwrap_err().to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn test_natives() {
+        eval_cases!(
+            "\
+fn shadow() {
+    let len = 10
+    len
+}
+let arr = [1]
+push(arr, 2)";
+            "map([[1, 2], [3], []], len)" => "[2, 1, 0]",
+            "shadow()" => "10",
+            "len(arr)" => "2",
+            "len(\"abc\")" => "3",
+        );
+    }
+
+    #[test]
+    fn test_native_write_back() {
+        for backend in [crate::Backend::TreeWalk, crate::Backend::Vm] {
+            let mut interp = crate::Interpreter::new();
+            interp.set_backend(backend);
+            // only push's array is written back, not the array it pushes
+            let res = interp.eval_str("let a = [1]\npush(a, a)\nlen(a)").unwrap();
+            assert_eq!(res, Some(Atom::Int(2)), "with {:?}", backend);
+            // and a callback's assignments aren't undone
+            let res = interp
+                .eval_str("let b = [1, 2]\nfn f(x) {\n    b = [7]\n    x\n}\nmap(b, f)\nb")
+                .unwrap();
+            assert_eq!(res, interp.eval_str("[7]").unwrap(), "with {:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut state = State::default();
+        state.register_fn("double", Some(1), |_, args| args[0].clone() * Atom::Int(2));
+
+        let expr = parse_expr(&mut Lexer::new(scan_tokens("double(4) + 1")));
+        assert_eq!(eval_expr(&expr, &mut state), Atom::Int(9));
+    }
 }
@@ -1,6 +1,17 @@
 use crate::block::Block;
+use crate::checker::Type;
+use crate::module::Module;
+use crate::resolver::Slot;
+use crate::statement::State;
+use crate::vm::Chunk;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::fmt;
-use std::ops::{Add, Div, Mul, Sub};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Shl, Shr, Sub};
+use std::rc::Rc;
 
 use crate::parser::S;
 
@@ -9,22 +20,58 @@ pub enum Atom {
     Str(String),
     Float(f64),
     Int(isize),
+    /// only used for integers that don't fit in an Int
+    BigInt(BigInt),
     Bool(bool),
     Identifier(String),
+    /// An identifier the resolver found the slot of
+    Local(Local),
     FnCall(FunctionCall),
-    Function(FunctionData),
+    /// Shared, since a function's body is never changed once it's
+    /// declared
+    Function(Rc<FunctionData>),
+    NativeFunction(NativeFunction),
+    Module(Module),
     Array(Vec<S>),
     Break,
 }
 
+// does an isize operation, redoing it with bignums if it overflows
+fn int_op(
+    a: isize,
+    b: isize,
+    op: fn(isize, isize) -> Option<isize>,
+    big_op: fn(BigInt, BigInt) -> BigInt,
+) -> Atom {
+    match op(a, b) {
+        Some(n) => Atom::Int(n),
+        None => Atom::from(big_op(BigInt::from(a), BigInt::from(b))),
+    }
+}
+
 impl PartialEq for Atom {
     fn eq(&self, rhs: &Self) -> bool {
         match (self, rhs) {
             (Atom::Str(a), Atom::Str(b)) => a == b,
             (Atom::Float(a), Atom::Float(b)) => a == b,
             (Atom::Int(a), Atom::Int(b)) => a == b,
+            (a, b) if a.is_int() && b.is_int() => a.to_bigint() == b.to_bigint(),
+            // numbers are equal if they have the same value, whatever
+            // their types, which is checked exactly so that big Ints
+            // aren't rounded to the nearest Float
+            (a, Atom::Float(f)) | (Atom::Float(f), a) if a.is_int() => {
+                f.fract() == 0.0 && BigInt::from_f64(*f).map_or(false, |f| f == a.to_bigint())
+            }
             (Atom::Bool(a), Atom::Bool(b)) => a == b,
             (Atom::Identifier(a), Atom::Identifier(b)) => a == b,
+            (Atom::Local(a), Atom::Local(b)) => a == b,
+            (Atom::Array(a), Atom::Array(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|pair| match pair {
+                        (S::Atom(x), S::Atom(y)) => x == y,
+                        _ => false,
+                    })
+            }
             _ => false,
         }
     }
@@ -40,10 +87,14 @@ impl Add for Atom {
             (Atom::Float(f), Atom::Str(s)) => Atom::Str(format!("{}{}", f, s)),
             (Atom::Str(s), Atom::Int(i)) => Atom::Str(format!("{}{}", s, i)),
             (Atom::Int(i), Atom::Str(s)) => Atom::Str(format!("{}{}", i, s)),
+            (Atom::Str(s), Atom::BigInt(i)) => Atom::Str(format!("{}{}", s, i)),
+            (Atom::BigInt(i), Atom::Str(s)) => Atom::Str(format!("{}{}", i, s)),
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a + b),
             (Atom::Int(a), Atom::Float(b)) => Atom::Float(*a as f64 + b),
             (Atom::Float(a), Atom::Int(b)) => Atom::Float(a + *b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a + b),
+            (Atom::Int(a), Atom::Int(b)) => int_op(*a, *b, isize::checked_add, |a, b| a + b),
+            (a, b) if a.is_int() && b.is_int() => Atom::from(a.to_bigint() + b.to_bigint()),
+            (a, b) if a.is_number() && b.is_number() => Atom::Float(a.to_f64() + b.to_f64()),
             _ => panic!("Add not implemented between {} and {}", self, rhs),
         }
     }
@@ -54,14 +105,13 @@ impl Sub for Atom {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a - b),
             (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 - b),
             (Atom::Float(a), Atom::Int(b)) => Atom::Float(a - b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a - b),
-            _ => todo!(),
+            (Atom::Int(a), Atom::Int(b)) => int_op(a, b, isize::checked_sub, |a, b| a - b),
+            (a, b) if a.is_int() && b.is_int() => Atom::from(a.to_bigint() - b.to_bigint()),
+            (a, b) if a.is_number() && b.is_number() => Atom::Float(a.to_f64() - b.to_f64()),
+            (a, b) => panic!("Subtract not implemented between {} and {}", a, b),
         }
     }
 }
@@ -71,53 +121,110 @@ impl Mul for Atom {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+            (Atom::Str(s), Atom::Int(n)) | (Atom::Int(n), Atom::Str(s)) if n >= 0 => {
+                Atom::Str(s.repeat(n as usize))
+            }
             (Atom::Float(a), Atom::Float(b)) => Atom::Float(a * b),
             (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 * b),
             (Atom::Float(a), Atom::Int(b)) => Atom::Float(a * b as f64),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a * b),
-            _ => todo!(),
+            (Atom::Int(a), Atom::Int(b)) => int_op(a, b, isize::checked_mul, |a, b| a * b),
+            (a, b) if a.is_int() && b.is_int() => Atom::from(a.to_bigint() * b.to_bigint()),
+            (a, b) if a.is_number() && b.is_number() => Atom::Float(a.to_f64() * b.to_f64()),
+            (a, b) => panic!("Multiply not implemented between {} and {}", a, b),
         }
     }
 }
 
+// `/` always gives a Float, `//` is for integer division
 impl Div for Atom {
     type Output = Atom;
 
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
-            (Atom::Float(a), Atom::Float(b)) => Atom::Float(a / b),
-            (Atom::Int(a), Atom::Float(b)) => Atom::Float(a as f64 / b),
-            (Atom::Float(a), Atom::Int(b)) => Atom::Float(a / b as f64),
-            (Atom::Int(a), Atom::Int(b)) => {
-                let res = a as f64 / b as f64;
-                if res.fract() != 0.0 {
-                    Atom::Float(res)
-                } else {
-                    Atom::Int(res as isize)
+            (a, b) if a.is_number() && b.is_number() => {
+                check_divisor(&b);
+                Atom::Float(a.to_f64() / b.to_f64())
+            }
+            (a, b) => panic!("Divide not implemented between {} and {}", a, b),
+        }
+    }
+}
+
+macro_rules! bitwise_op {
+    ( $trait:ident, $method:ident, $name:expr ) => {
+        impl $trait for Atom {
+            type Output = Atom;
+
+            fn $method(self, rhs: Self) -> Self::Output {
+                match (self, rhs) {
+                    (Atom::Int(a), Atom::Int(b)) => Atom::Int(a.$method(b)),
+                    (a, b) if a.is_int() && b.is_int() => {
+                        Atom::from(a.to_bigint().$method(b.to_bigint()))
+                    }
+                    (a, b) => panic!("{} not implemented between {} and {}", $name, a, b),
                 }
-            },
-            _ => todo!(),
+            }
+        }
+    };
+}
+
+bitwise_op!(BitAnd, bitand, "Bitwise and");
+bitwise_op!(BitOr, bitor, "Bitwise or");
+bitwise_op!(BitXor, bitxor, "Bitwise xor");
+
+// shifts go through BigInt so that left shifts can't overflow
+impl Shl for Atom {
+    type Output = Atom;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (a, b) if a.is_int() && b.is_int() => Atom::from(a.to_bigint() << shift_amount(&b)),
+            (a, b) => panic!("Shift not implemented between {} and {}", a, b),
         }
     }
 }
 
+impl Shr for Atom {
+    type Output = Atom;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (a, b) if a.is_int() && b.is_int() => Atom::from(a.to_bigint() >> shift_amount(&b)),
+            (a, b) => panic!("Shift not implemented between {} and {}", a, b),
+        }
+    }
+}
+
+fn shift_amount(n: &Atom) -> usize {
+    match n {
+        Atom::Int(n) if *n >= 0 => *n as usize,
+        Atom::Int(_) => panic!("Negative shift amount {}", n),
+        _ => panic!("Shift amount {} is too large", n),
+    }
+}
+
+fn check_divisor(n: &Atom) {
+    let zero = match n {
+        Atom::Int(n) => *n == 0,
+        Atom::Float(n) => *n == 0.0,
+        _ => false,
+    };
+    if zero {
+        panic!("Division by zero");
+    }
+}
+
 impl std::cmp::PartialOrd for Atom {
     fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
         match (self, rhs) {
-            (Atom::Str(_), Atom::Str(_)) => todo!(),
-            (Atom::Str(_), Atom::Float(_)) => todo!(),
-            (Atom::Float(_), Atom::Str(_)) => todo!(),
+            (Atom::Str(a), Atom::Str(b)) => a.partial_cmp(b),
             (Atom::Float(a), Atom::Float(b)) => a.partial_cmp(b),
             (Atom::Float(a), Atom::Int(b)) => a.partial_cmp(&(*b as f64)),
             (Atom::Int(a), Atom::Float(b)) => (*a as f64).partial_cmp(b),
             (Atom::Int(a), Atom::Int(b)) => a.partial_cmp(b),
-            _ => todo!(),
+            (a, b) if a.is_int() && b.is_int() => a.to_bigint().partial_cmp(&b.to_bigint()),
+            (a, b) if a.is_number() && b.is_number() => a.to_f64().partial_cmp(&b.to_f64()),
+            _ => panic!("Can't compare {} and {}", self, rhs),
         }
     }
 }
@@ -128,12 +235,36 @@ impl fmt::Display for Atom {
             Atom::Str(s) => write!(f, "{}", s),
             Atom::Float(n) => write!(f, "{}", n),
             Atom::Int(n) => write!(f, "{}", n),
+            Atom::BigInt(n) => write!(f, "{}", n),
             Atom::Bool(b) => write!(f, "{}", b),
             Atom::Identifier(name) => write!(f, "(Identifier {})", name),
+            Atom::Local(Local { name, slot }) => {
+                write!(f, "(Local {} {} {})", name, slot.depth, slot.index)
+            }
             Atom::Break => write!(f, "Break"),
-            Atom::FnCall(FunctionCall { name, args }) => write!(f, "{}({:?})", name, args),
+            Atom::FnCall(FunctionCall { name, args }) => {
+                write!(f, "(call {}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
             Atom::Function(_) => write!(f, "FunctionData"),
-            Atom::Array(a) => write!(f, "{:?}", a),
+            Atom::NativeFunction(n) => write!(f, "NativeFunction({})", n.name),
+            Atom::Module(m) => write!(f, "<module {}>", m.name),
+            Atom::Array(a) => {
+                write!(f, "[")?;
+                for (i, x) in a.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match x {
+                        S::Atom(Atom::Str(s)) => write!(f, "{:?}", s)?,
+                        x => write!(f, "{}", x)?,
+                    }
+                }
+                write!(f, "]")
+            }
         }
     }
 }
@@ -142,18 +273,125 @@ impl Atom {
     pub fn negate(self) -> Atom {
         match self {
             Atom::Str(_) => todo!(),
-            Atom::Float(n) => Atom::Float(-1.0 * n),
-            Atom::Int(n) => Atom::Int(-1 * n),
+            Atom::Float(n) => Atom::Float(-n),
+            Atom::Int(n) => match n.checked_neg() {
+                Some(n) => Atom::Int(n),
+                None => Atom::from(-BigInt::from(n)),
+            },
+            Atom::BigInt(n) => Atom::from(-n),
             Atom::Bool(b) => Atom::Bool(!b),
             _ => todo!(),
         }
     }
 
+    /// Integer division that rounds down, which matches `%`
+    /// so that `a == (a // b) * b + a % b`
+    pub fn floor_div(&self, rhs: &Atom) -> Atom {
+        check_divisor(rhs);
+        match (self, rhs) {
+            (Atom::Int(a), Atom::Int(b)) if !(*a == isize::MIN && *b == -1) => {
+                Atom::Int(a.div_floor(b))
+            }
+            (a, b) if a.is_int() && b.is_int() => {
+                Atom::from(a.to_bigint().div_floor(&b.to_bigint()))
+            }
+            (a, b) if a.is_number() && b.is_number() => {
+                Atom::Float((a.to_f64() / b.to_f64()).floor())
+            }
+            (a, b) => panic!("Floor divide not implemented between {} and {}", a, b),
+        }
+    }
+
+    /// The result has the same sign as `rhs`, like in Python
     pub fn modulus(&self, rhs: &Atom) -> Atom {
+        check_divisor(rhs);
         match (self, rhs) {
-            (Atom::Float(a), Atom::Float(b)) => Atom::Float(a % b),
-            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a % b),
-            _ => todo!(),
+            (Atom::Int(_), Atom::Int(-1)) => Atom::Int(0),
+            (Atom::Int(a), Atom::Int(b)) => Atom::Int(a.mod_floor(b)),
+            (a, b) if a.is_int() && b.is_int() => {
+                Atom::from(a.to_bigint().mod_floor(&b.to_bigint()))
+            }
+            (a, b) if a.is_number() && b.is_number() => {
+                let (a, b) = (a.to_f64(), b.to_f64());
+                let res = a % b;
+                if res != 0.0 && (res < 0.0) != (b < 0.0) {
+                    Atom::Float(res + b)
+                } else {
+                    Atom::Float(res)
+                }
+            }
+            (a, b) => panic!("Modulo not implemented between {} and {}", a, b),
+        }
+    }
+
+    /// Ints raised to non negative Int powers stay Ints
+    pub fn pow(&self, rhs: &Atom) -> Atom {
+        match (self, rhs) {
+            (a, Atom::Int(b)) if a.is_int() && *b >= 0 => {
+                let exp = match u32::try_from(*b) {
+                    Ok(exp) => exp,
+                    Err(_) => panic!("Exponent {} is too large", b),
+                };
+                match a {
+                    Atom::Int(a) => match a.checked_pow(exp) {
+                        Some(n) => Atom::Int(n),
+                        None => Atom::from(BigInt::from(*a).pow(exp)),
+                    },
+                    a => Atom::from(a.to_bigint().pow(exp)),
+                }
+            }
+            (a, b) if a.is_number() && b.is_number() => Atom::Float(a.to_f64().powf(b.to_f64())),
+            (a, b) => panic!("Power not implemented between {} and {}", a, b),
+        }
+    }
+
+    /// The name scripts see from `type_of`, Ints and BigInts
+    /// are both "int"
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Atom::Str(_) => "str",
+            Atom::Float(_) => "float",
+            Atom::Int(_) | Atom::BigInt(_) => "int",
+            Atom::Bool(_) => "bool",
+            Atom::Function(_) | Atom::NativeFunction(_) => "function",
+            Atom::Array(_) => "array",
+            Atom::Module(_) => "module",
+            Atom::Identifier(_) | Atom::Local(_) | Atom::FnCall(_) | Atom::Break => {
+                unreachable!("{:?} isn't a value", self)
+            }
+        }
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Atom::Int(_) | Atom::BigInt(_))
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Atom::Int(_) | Atom::BigInt(_) | Atom::Float(_))
+    }
+
+    /// Whether two values count as the same type when reassigning
+    /// a variable, which doesn't distinguish between Int and BigInt
+    pub fn same_type(&self, rhs: &Atom) -> bool {
+        (self.is_int() && rhs.is_int())
+            || std::mem::discriminant(self) == std::mem::discriminant(rhs)
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        match self {
+            Atom::Int(n) => BigInt::from(*n),
+            Atom::BigInt(n) => n.clone(),
+            _ => panic!("{} is not an int", self),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Atom::Float(n) => *n,
+            Atom::Int(n) => *n as f64,
+            // to_f64 saturates to infinity rather than failing
+            Atom::BigInt(n) => n.to_f64().unwrap(),
+            _ => panic!("{} is not a number", self),
         }
     }
 
@@ -189,18 +427,54 @@ impl Atom {
                     )
                 }
             }
+            (Atom::Str(s), Atom::Int(i)) if i >= &0 => match s.chars().nth(*i as usize) {
+                Some(c) => Atom::Str(c.to_string()),
+                None => panic!(
+                    "Tried getting {:?}th character of string with length {}",
+                    i,
+                    s.chars().count()
+                ),
+            },
             _ => panic!("Can't index {:?} by {:?}", self, rhs),
         }
     }
 
-    pub fn access(&self, _rhs: &S) -> Atom {
-        todo!();
-        // match (self, rhs) {
-        //     _ => panic!("Can't access field {} of {}", rhs, self),
-        // }
+    /// Slices a string or array by character or element indices,
+    /// going until the end if there's no `end`
+    pub fn slice(&self, start: &Atom, end: Option<&Atom>) -> Atom {
+        let len = match self {
+            Atom::Str(s) => s.chars().count(),
+            Atom::Array(a) => a.len(),
+            _ => panic!("Can't slice {}", self),
+        };
+        let bound = |a: &Atom| match a {
+            Atom::Int(i) if *i >= 0 && *i as usize <= len => *i as usize,
+            _ => panic!("Invalid slice index {} for length {}", a, len),
+        };
+        let start = bound(start);
+        let end = end.map(bound).unwrap_or(len);
+        if start > end {
+            panic!("Slice start {} is after end {}", start, end);
+        }
+
+        match self {
+            Atom::Str(s) => Atom::Str(s.chars().skip(start).take(end - start).collect()),
+            Atom::Array(a) => Atom::Array(a[start..end].to_vec()),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn access(&self, rhs: &S) -> Atom {
+        panic!("Can't access {} of {}", rhs, self)
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub name: String,
+    pub slot: Slot,
+}
+
 #[derive(Debug, Clone)]
 pub struct FunctionCall {
     pub name: String,
@@ -216,7 +490,24 @@ impl PartialEq for FunctionCall {
 #[derive(Debug, Clone)]
 pub struct FunctionData {
     pub arg_names: Vec<String>,
+    /// None for arguments without an annotation
+    pub arg_types: Vec<Option<Type>>,
+    pub return_type: Option<Type>,
     pub fn_block: Block,
+    /// The body compiled to bytecode, once the VM has called the
+    /// function
+    pub code: RefCell<Option<Rc<Chunk>>>,
+}
+
+impl FunctionData {
+    /// The body compiled to bytecode, which is compiled the first time
+    /// it's needed and shared by every copy of the function after that.
+    pub fn code(&self) -> Rc<Chunk> {
+        self.code
+            .borrow_mut()
+            .get_or_insert_with(|| Rc::new(crate::vm::compile_function(&self.fn_block)))
+            .clone()
+    }
 }
 
 impl PartialEq for FunctionData {
@@ -225,3 +516,77 @@ impl PartialEq for FunctionData {
         false
     }
 }
+
+pub type NativeFn = Rc<dyn Fn(&mut State, &mut [Atom]) -> Atom>;
+
+/// A function implemented in Rust. Arguments are evaluated before
+/// the call.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    /// None if the function takes any number of arguments
+    pub arity: Option<usize>,
+    /// The arguments the function modifies in place, which are written
+    /// back afterwards if they were given as plain variable names
+    pub mutates: &'static [usize],
+    pub func: NativeFn,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+/// Width and precision for a value in an interpolated string, written
+/// after a colon like `{x:8.2}`. A leading 0 on the width pads numbers
+/// with zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub zero_pad: bool,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Option<FormatSpec> {
+        // usize::from_str would take a sign too
+        let number = |digits: &str| -> Option<usize> {
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        };
+        let (width, precision) = match spec.find('.') {
+            Some(i) => (&spec[..i], Some(number(&spec[i + 1..])?)),
+            None => (spec, None),
+        };
+
+        Some(FormatSpec {
+            width: if width.is_empty() {
+                None
+            } else {
+                Some(number(width)?)
+            },
+            precision,
+            zero_pad: width.starts_with('0'),
+        })
+    }
+
+    pub fn format(&self, a: &Atom) -> String {
+        let s = match (a, self.precision) {
+            (Atom::Float(n), Some(p)) => format!("{:.*}", p, n),
+            _ => a.to_string(),
+        };
+        let width = self.width.unwrap_or(0);
+
+        match a {
+            a if a.is_number() && self.zero_pad => match s.strip_prefix('-') {
+                Some(digits) => format!("-{:0>w$}", digits, w = width.saturating_sub(1)),
+                None => format!("{:0>w$}", s, w = width),
+            },
+            a if a.is_number() => format!("{:>w$}", s, w = width),
+            _ => format!("{:<w$}", s, w = width),
+        }
+    }
+}
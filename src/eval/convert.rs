@@ -0,0 +1,136 @@
+// conversions between Rust values and slang values for the embedding API
+
+use std::convert::TryFrom;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::eval::atom::Atom;
+use crate::parser::S;
+
+impl From<isize> for Atom {
+    fn from(n: isize) -> Self {
+        Atom::Int(n)
+    }
+}
+
+impl From<i32> for Atom {
+    fn from(n: i32) -> Self {
+        Atom::Int(n as isize)
+    }
+}
+
+/// Gives an Int if the number is small enough
+impl From<BigInt> for Atom {
+    fn from(n: BigInt) -> Self {
+        match n.to_isize() {
+            Some(n) => Atom::Int(n),
+            None => Atom::BigInt(n),
+        }
+    }
+}
+
+impl From<f64> for Atom {
+    fn from(n: f64) -> Self {
+        Atom::Float(n)
+    }
+}
+
+impl From<bool> for Atom {
+    fn from(b: bool) -> Self {
+        Atom::Bool(b)
+    }
+}
+
+impl From<String> for Atom {
+    fn from(s: String) -> Self {
+        Atom::Str(s)
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(s: &str) -> Self {
+        Atom::Str(s.to_string())
+    }
+}
+
+impl<T: Into<Atom>> From<Vec<T>> for Atom {
+    fn from(v: Vec<T>) -> Self {
+        Atom::Array(v.into_iter().map(|x| S::Atom(x.into())).collect())
+    }
+}
+
+impl TryFrom<Atom> for isize {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Int(n) => Ok(n),
+            _ => Err(format!("{} is not an int", a)),
+        }
+    }
+}
+
+impl TryFrom<Atom> for BigInt {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Int(n) => Ok(BigInt::from(n)),
+            Atom::BigInt(n) => Ok(n),
+            _ => Err(format!("{} is not an int", a)),
+        }
+    }
+}
+
+impl TryFrom<Atom> for f64 {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Float(n) => Ok(n),
+            Atom::Int(n) => Ok(n as f64),
+            Atom::BigInt(n) => Ok(n.to_f64().unwrap()),
+            _ => Err(format!("{} is not a number", a)),
+        }
+    }
+}
+
+impl TryFrom<Atom> for bool {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Bool(b) => Ok(b),
+            _ => Err(format!("{} is not a bool", a)),
+        }
+    }
+}
+
+impl TryFrom<Atom> for String {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Str(s) => Ok(s),
+            _ => Err(format!("{} is not a string", a)),
+        }
+    }
+}
+
+impl<T: TryFrom<Atom, Error = String>> TryFrom<Atom> for Vec<T> {
+    type Error = String;
+
+    fn try_from(a: Atom) -> Result<Self, Self::Error> {
+        match a {
+            Atom::Array(arr) => arr
+                .into_iter()
+                .map(|s| match s {
+                    S::Atom(a) => T::try_from(a),
+                    s => Err(format!("{} is not a value", s)),
+                })
+                .collect(),
+            _ => Err(format!("{} is not an array", a)),
+        }
+    }
+}
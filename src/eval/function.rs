@@ -1,111 +1,325 @@
+use std::cmp::Ordering;
 use std::convert::TryInto;
 
-use crate::eval::atom::{FunctionCall, FunctionData};
-use crate::statement::Declaration;
-use crate::statement::Stmt;
+use crate::checker::Type;
+use crate::eval::atom::{FunctionCall, FunctionData, NativeFunction};
+use crate::parser::S;
+use crate::statement::Scope;
 use crate::Atom;
+use crate::Backend;
 use crate::State;
-use crate::{block::Block, parser::S};
 
 use super::eval_expr;
 
+mod math;
+mod string;
+mod types;
+
 pub fn eval_function_call(f: &FunctionCall, state: &mut State) -> Option<Atom> {
     let FunctionCall { name, args } = f;
 
-    if name == "push" {
-        let res = array_push(&args[0], &args[1], state);
-        return Some(res);
+    match state.get_variable(name) {
+        Some(Atom::Function(fn_data)) => {
+            let fn_data = fn_data.clone();
+            let arg_vals = args.iter().map(|a| eval_expr(a, state)).collect();
+            call_function(&fn_data, arg_vals, state)
+        }
+        Some(Atom::NativeFunction(native)) => {
+            let native = native.clone();
+            let mut arg_vals: Vec<Atom> = args.iter().map(|a| eval_expr(a, state)).collect();
+            let res = call_native(&native, &mut arg_vals, state);
+
+            // write back the arguments the function modifies if they
+            // were passed by name, this is what lets push modify its array
+            for &i in native.mutates {
+                let val = arg_vals[i].clone();
+                match &args[i] {
+                    S::Atom(Atom::Identifier(arg_name)) => state.modify_variable(arg_name, val),
+                    S::Atom(Atom::Local(local)) => state.set_local(local.slot, val),
+                    _ => {}
+                }
+            }
+
+            Some(res)
+        }
+        Some(v) => panic!("{} is not a function", v),
+        None => panic!("Function {} is undefined", name),
     }
+}
 
-    if name == "len" {
-        let res = array_len(&args[0], state);
-        return Some(res);
+/// Calls a function with already evaluated arguments.
+pub fn call_function(fn_data: &FunctionData, args: Vec<Atom>, state: &mut State) -> Option<Atom> {
+    enter_function(fn_data, args, state);
+    let res = fn_data.fn_block.execute_unscoped(state);
+    state.scopes.pop();
+    check_return(&fn_data.return_type, res)
+}
+
+/// Pushes the scope a function runs in, with its arguments declared
+/// in it.
+pub fn enter_function(fn_data: &FunctionData, args: Vec<Atom>, state: &mut State) {
+    let FunctionData {
+        arg_names,
+        arg_types,
+        fn_block,
+        ..
+    } = fn_data;
+    if args.len() != arg_names.len() {
+        panic!(
+            "Function takes {} arguments but {} were given",
+            arg_names.len(),
+            args.len()
+        );
     }
 
-    if name == "round" {
-        let res = float_round(&args[0], state);
-        return Some(res);
+    state.scopes.push(Scope::new(fn_block.locals.clone()));
+    for ((name, ty), val) in arg_names.iter().zip(arg_types).zip(args) {
+        match fn_block.locals.iter().position(|n| n == name) {
+            Some(i) => state.declare_local(name, i, val, *ty),
+            None => state.declare_value(name, val, *ty),
+        }
     }
+}
 
-    if name == "floor" {
-        let res = float_floor(&args[0], state);
-        return Some(res);
+/// Checks what a function returned against its annotation.
+pub fn check_return(return_type: &Option<Type>, res: Option<Atom>) -> Option<Atom> {
+    match (return_type, &res) {
+        (Some(ty), Some(val)) if !ty.matches(val) => panic!(
+            "Function returned {} but is declared to return {}",
+            val.type_name(),
+            ty
+        ),
+        _ => res,
     }
+}
 
-    if let Some(Atom::Function(fn_data)) = state.get_variable(name) {
-        let FunctionData {
-            arg_names,
-            fn_block,
-        } = fn_data;
-        let mut full_block_statements: Vec<Stmt> =
-            Vec::with_capacity(args.len() + fn_block.statements.len());
-        args.iter()
-            .zip(arg_names.iter())
-            .for_each(|(arg_val, arg_name)| {
-                full_block_statements.push(Stmt::Dec(Declaration {
-                    lhs: arg_name.to_string(),
-                    rhs: arg_val.clone(),
-                    alias: true,
-                    plus_or_minus: None,
-                }))
-            });
-
-        full_block_statements.append(&mut fn_block.clone().statements);
-
-        let mut full_block = Block::new(full_block_statements);
-        full_block.execute(state)
-    } else {
-        panic!("Function {} is undefined", name);
+pub fn call_native(native: &NativeFunction, args: &mut [Atom], state: &mut State) -> Atom {
+    if let Some(arity) = native.arity {
+        if arity != args.len() {
+            panic!(
+                "Function {} takes {} arguments but {} were given",
+                native.name,
+                arity,
+                args.len()
+            );
+        }
     }
+
+    (native.func)(state, args)
 }
 
-fn array_push(a: &S, e: &S, state: &mut State) -> Atom {
-    match (a, e) {
-        (S::Atom(Atom::Identifier(arr_name)), s) => {
-            let new_val = eval_expr(s, state);
-            if let Some(Atom::Array(a)) = state.get_variable(&arr_name) {
-                // this could be sped up a lot by adding a get mut function
-                // to State
-                let mut new_arr = a.clone();
-                new_arr.push(S::Atom(new_val.clone()));
-                state.declare(Declaration {
-                    lhs: arr_name.to_string(),
-                    rhs: S::Atom(Atom::Array(new_arr)),
-                    alias: false,
-                    plus_or_minus: None,
-                });
-                new_val
-            } else {
-                panic!("{} is not an array or undefined", arr_name);
-            }
+/// Calls a function value with already evaluated arguments.
+pub fn call_value(f: &Atom, args: Vec<Atom>, state: &mut State) -> Option<Atom> {
+    match f {
+        Atom::Function(fn_data) => match state.backend {
+            Backend::TreeWalk => call_function(fn_data, args, state),
+            Backend::Vm => crate::vm::call(fn_data, args, state),
+        },
+        Atom::NativeFunction(native) => {
+            let mut args = args;
+            Some(call_native(native, &mut args, state))
         }
-        _ => panic!("Can't push"),
+        _ => panic!("{} is not a function", f),
+    }
+}
+
+/// What `exit()` unwinds the stack with. It's raised with
+/// `resume_unwind` so the panic hook doesn't report it, and the
+/// interpreter turns it back into an exit code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exit(pub i32);
+
+pub fn install_builtins(state: &mut State) {
+    state.register_fn("exit", None, exit);
+    state.register_mutating_fn("push", Some(2), &[0], array_push);
+    state.register_fn("len", Some(1), array_len);
+    state.register_fn("map", Some(2), array_map);
+    state.register_fn("filter", Some(2), array_filter);
+    state.register_fn("reduce", Some(3), array_reduce);
+    state.register_fn("sort", Some(1), array_sort);
+    state.register_fn("sort_by", Some(2), array_sort_by);
+    state.register_fn("any", Some(2), array_any);
+    state.register_fn("all", Some(2), array_all);
+    state.register_fn("find", Some(2), array_find);
+    state.register_fn("zip", Some(2), array_zip);
+    state.register_fn("enumerate", Some(1), array_enumerate);
+    state.register_fn("contains", Some(2), contains);
+
+    string::install(state);
+    math::install(state);
+    types::install(state);
+}
+
+// calls the function argument of a builtin, which has to return something
+fn apply(f: &Atom, args: Vec<Atom>, state: &mut State) -> Atom {
+    match call_value(f, args, state) {
+        Some(a) => a,
+        None => panic!("Function passed to builtin returned no value"),
     }
 }
 
-fn array_len(a: &S, state: &mut State) -> Atom {
-    if let S::Atom(Atom::Identifier(arr_name)) = a {
-        if let Some(Atom::Array(a)) = state.get_variable(&arr_name) {
-            return Atom::Int(a.len().try_into().unwrap());
+fn exit(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let code = match args {
+        [] => 0,
+        // the OS only keeps the low 8 bits, so 256 would look like
+        // success
+        [Atom::Int(n)] if (0..=255).contains(n) => *n as i32,
+        [Atom::Int(_)] | [Atom::BigInt(_)] => {
+            panic!("Exit code {} has to be from 0 to 255", args[0])
         }
+        [v] => panic!("Exit code has to be an int, not {}", v.type_name()),
+        _ => panic!(
+            "Function exit takes 0 or 1 arguments but {} were given",
+            args.len()
+        ),
+    };
+    std::panic::resume_unwind(Box::new(Exit(code)))
+}
+
+fn array_elements(a: &Atom) -> Vec<Atom> {
+    match a {
+        Atom::Array(arr) => arr
+            .iter()
+            .map(|s| match s {
+                S::Atom(a) => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect(),
+        v => panic!("{} is not an array", v),
     }
-    panic!("{:?} is not an array", a)
 }
 
-fn float_round(v: &S, state: &mut State) -> Atom {
-    let f = eval_expr(v, state);
-    match f {
-        Atom::Float(n) => Atom::Int(n.round() as isize),
-        Atom::Int(n) => Atom::Int(n),
-        _ => panic!("{:?} is not a number", v),
+fn to_array(atoms: Vec<Atom>) -> Atom {
+    Atom::Array(atoms.into_iter().map(S::Atom).collect())
+}
+
+fn array_push(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let new_val = args[1].clone();
+    match &mut args[0] {
+        Atom::Array(a) => {
+            a.push(S::Atom(new_val.clone()));
+            new_val
+        }
+        v => panic!("{} is not an array", v),
     }
 }
 
-fn float_floor(v: &S, state: &mut State) -> Atom {
-    let f = eval_expr(v, state);
-    match f {
-        Atom::Float(n) => Atom::Int(n.floor() as isize),
-        Atom::Int(n) => Atom::Int(n),
-        _ => panic!("{:?} is not a number", v),
+fn array_len(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        Atom::Array(a) => Atom::Int(a.len().try_into().unwrap()),
+        Atom::Str(s) => string::str_len(s),
+        v => panic!("{} is not an array or string", v),
     }
 }
+
+fn contains(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        Atom::Array(a) => Atom::Bool(a.iter().any(|s| matches!(s, S::Atom(x) if x == &args[1]))),
+        Atom::Str(s) => string::str_contains(s, &args[1]),
+        v => panic!("{} is not an array or string", v),
+    }
+}
+
+fn array_map(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[1];
+    let res = array_elements(&args[0])
+        .into_iter()
+        .map(|x| apply(f, vec![x], state))
+        .collect();
+    to_array(res)
+}
+
+fn array_filter(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[1];
+    let res = array_elements(&args[0])
+        .into_iter()
+        .filter(|x| apply(f, vec![x.clone()], state) == Atom::Bool(true))
+        .collect();
+    to_array(res)
+}
+
+fn array_reduce(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[2];
+    array_elements(&args[0])
+        .into_iter()
+        .fold(args[1].clone(), |acc, x| apply(f, vec![acc, x], state))
+}
+
+fn compare(a: &Atom, b: &Atom) -> Ordering {
+    match a.partial_cmp(b) {
+        Some(ord) => ord,
+        None => panic!("Can't compare {} and {}", a, b),
+    }
+}
+
+fn array_sort(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let mut arr = array_elements(&args[0]);
+    arr.sort_by(compare);
+    to_array(arr)
+}
+
+// sorts by the key f returns for each element, so f is
+// only called once per element
+fn array_sort_by(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[1];
+    let mut keyed: Vec<(Atom, Atom)> = array_elements(&args[0])
+        .into_iter()
+        .map(|x| (apply(f, vec![x.clone()], state), x))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| compare(a, b));
+    to_array(keyed.into_iter().map(|(_, x)| x).collect())
+}
+
+fn array_any(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[1];
+    Atom::Bool(
+        array_elements(&args[0])
+            .into_iter()
+            .any(|x| apply(f, vec![x], state) == Atom::Bool(true)),
+    )
+}
+
+fn array_all(state: &mut State, args: &mut [Atom]) -> Atom {
+    let f = &args[1];
+    Atom::Bool(
+        array_elements(&args[0])
+            .into_iter()
+            .all(|x| apply(f, vec![x], state) == Atom::Bool(true)),
+    )
+}
+
+// returns the index of the first element f accepts, or -1
+//
+// strings are searched for a substring instead
+fn array_find(state: &mut State, args: &mut [Atom]) -> Atom {
+    if let Atom::Str(s) = &args[0] {
+        return string::str_find(s, &args[1]);
+    }
+
+    let f = &args[1];
+    match array_elements(&args[0])
+        .into_iter()
+        .position(|x| apply(f, vec![x], state) == Atom::Bool(true))
+    {
+        Some(i) => Atom::Int(i.try_into().unwrap()),
+        None => Atom::Int(-1),
+    }
+}
+
+fn array_zip(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let res = array_elements(&args[0])
+        .into_iter()
+        .zip(array_elements(&args[1]))
+        .map(|(x, y)| to_array(vec![x, y]))
+        .collect();
+    to_array(res)
+}
+
+fn array_enumerate(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let res = array_elements(&args[0])
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| to_array(vec![Atom::Int(i.try_into().unwrap()), x]))
+        .collect();
+    to_array(res)
+}
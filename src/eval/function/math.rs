@@ -0,0 +1,160 @@
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed};
+
+use super::{array_elements, compare};
+use crate::Atom;
+use crate::State;
+
+pub fn install(state: &mut State) {
+    let globals = &mut state.scopes[0].vars;
+    globals.insert("PI".to_string(), Atom::Float(std::f64::consts::PI));
+    globals.insert("E".to_string(), Atom::Float(std::f64::consts::E));
+
+    state.register_fn("round", Some(1), |_, args| round_with(&args[0], f64::round));
+    state.register_fn("floor", Some(1), |_, args| round_with(&args[0], f64::floor));
+    state.register_fn("ceil", Some(1), |_, args| round_with(&args[0], f64::ceil));
+    state.register_fn("abs", Some(1), math_abs);
+    state.register_fn("min", None, |_, args| {
+        extreme("min", args, std::cmp::Ordering::Less)
+    });
+    state.register_fn("max", None, |_, args| {
+        extreme("max", args, std::cmp::Ordering::Greater)
+    });
+    state.register_fn("pow", Some(2), |_, args| args[0].pow(&args[1]));
+    state.register_fn("sqrt", Some(1), math_sqrt);
+    state.register_fn("log", Some(1), math_log);
+    state.register_fn("exp", Some(1), |_, args| float_fn(&args[0], f64::exp));
+    state.register_fn("sin", Some(1), |_, args| float_fn(&args[0], f64::sin));
+    state.register_fn("cos", Some(1), |_, args| float_fn(&args[0], f64::cos));
+    state.register_fn("tan", Some(1), |_, args| float_fn(&args[0], f64::tan));
+    state.register_fn("atan2", Some(2), |_, args| {
+        Atom::Float(number_arg(&args[0]).atan2(number_arg(&args[1])))
+    });
+
+    install_random(state);
+}
+
+pub fn number_arg(a: &Atom) -> f64 {
+    match f64::try_from(a.clone()) {
+        Ok(n) => n,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Converts a whole float to an Int, or a BigInt if it's too big
+pub fn float_to_int(n: f64) -> Atom {
+    match BigInt::from_f64(n) {
+        Some(n) => Atom::from(n),
+        None => panic!("Can't convert {} to an int", n),
+    }
+}
+
+fn float_fn(a: &Atom, f: fn(f64) -> f64) -> Atom {
+    Atom::Float(f(number_arg(a)))
+}
+
+// round, floor and ceil give ints, which they leave alone
+fn round_with(a: &Atom, f: fn(f64) -> f64) -> Atom {
+    match a {
+        Atom::Float(n) => float_to_int(f(*n)),
+        n if n.is_int() => n.clone(),
+        v => panic!("{} is not a number", v),
+    }
+}
+
+fn math_abs(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        Atom::Float(n) => Atom::Float(n.abs()),
+        Atom::Int(n) => match n.checked_abs() {
+            Some(n) => Atom::Int(n),
+            None => Atom::from(BigInt::from(*n).abs()),
+        },
+        Atom::BigInt(n) => Atom::from(n.abs()),
+        v => panic!("{} is not a number", v),
+    }
+}
+
+fn math_sqrt(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match number_arg(&args[0]) {
+        n if n < 0.0 => panic!("Can't take the square root of {}", n),
+        n => Atom::Float(n.sqrt()),
+    }
+}
+
+fn math_log(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match number_arg(&args[0]) {
+        n if n <= 0.0 => panic!("Can't take the log of {}", n),
+        n => Atom::Float(n.ln()),
+    }
+}
+
+// min and max take either an array or the values as arguments
+fn extreme(name: &str, args: &[Atom], want: std::cmp::Ordering) -> Atom {
+    let vals = match args {
+        [arr @ Atom::Array(_)] => array_elements(arr),
+        _ => args.to_vec(),
+    };
+
+    vals.into_iter()
+        .reduce(|best, x| if compare(&x, &best) == want { x } else { best })
+        .unwrap_or_else(|| panic!("{} needs at least one value", name))
+}
+
+/// splitmix64, which is plenty for scripts and easy to seed
+struct Rng(Cell<u64>);
+
+impl Rng {
+    fn next(&self) -> u64 {
+        let state = self.0.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.0.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // a float in [0, 1)
+    fn next_float(&self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// random numbers come from the clock unless the script calls seed,
+// after which they're the same every run
+fn install_random(state: &mut State) {
+    let time_seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let rng = Rc::new(Rng(Cell::new(time_seed)));
+
+    let r = rng.clone();
+    state.register_fn("seed", Some(1), move |_, args| match &args[0] {
+        Atom::Int(n) => {
+            r.0.set(*n as u64);
+            Atom::Int(*n)
+        }
+        v => panic!("Seed {} is not an int", v),
+    });
+
+    let r = rng.clone();
+    state.register_fn("random", Some(0), move |_, _| Atom::Float(r.next_float()));
+
+    // both ends are included
+    state.register_fn("random_int", Some(2), move |_, args| {
+        match (&args[0], &args[1]) {
+            (Atom::Int(lo), Atom::Int(hi)) if lo <= hi => {
+                let range = (*hi as i128 - *lo as i128 + 1) as u128;
+                let offset = (rng.next() as u128 % range) as i128;
+                Atom::Int((*lo as i128 + offset) as isize)
+            }
+            (Atom::Int(lo), Atom::Int(hi)) => panic!("Empty range {} to {}", lo, hi),
+            (lo, hi) => panic!("random_int takes two ints, not {} and {}", lo, hi),
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use std::convert::TryInto;
+
+use crate::parser::S;
+use crate::Atom;
+use crate::State;
+
+pub fn install(state: &mut State) {
+    state.register_fn("split", Some(2), str_split);
+    state.register_fn("join", Some(2), str_join);
+    state.register_fn("trim", Some(1), str_trim);
+    state.register_fn("upper", Some(1), str_upper);
+    state.register_fn("lower", Some(1), str_lower);
+    state.register_fn("replace", Some(3), str_replace);
+    state.register_fn("starts_with", Some(2), str_starts_with);
+    state.register_fn("ends_with", Some(2), str_ends_with);
+    state.register_fn("chars", Some(1), str_chars);
+    state.register_fn("ord", Some(1), str_ord);
+    state.register_fn("chr", Some(1), str_chr);
+}
+
+fn string_arg(a: &Atom) -> &str {
+    match a {
+        Atom::Str(s) => s,
+        v => panic!("{} is not a string", v),
+    }
+}
+
+fn to_str_array<'a>(strs: impl Iterator<Item = &'a str>) -> Atom {
+    Atom::Array(strs.map(|s| S::Atom(Atom::Str(s.to_string()))).collect())
+}
+
+pub fn str_len(s: &str) -> Atom {
+    Atom::Int(s.chars().count().try_into().unwrap())
+}
+
+// returns the character index of sub in s, or -1
+pub fn str_find(s: &str, sub: &Atom) -> Atom {
+    match s.find(string_arg(sub)) {
+        Some(i) => Atom::Int(s[..i].chars().count().try_into().unwrap()),
+        None => Atom::Int(-1),
+    }
+}
+
+pub fn str_contains(s: &str, sub: &Atom) -> Atom {
+    Atom::Bool(s.contains(string_arg(sub)))
+}
+
+// splitting by an empty string splits into characters
+fn str_split(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let s = string_arg(&args[0]);
+    match string_arg(&args[1]) {
+        "" => to_str_array(s.split("").filter(|c| !c.is_empty())),
+        sep => to_str_array(s.split(sep)),
+    }
+}
+
+fn str_join(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let sep = string_arg(&args[1]);
+    match &args[0] {
+        Atom::Array(arr) => Atom::Str(
+            arr.iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>()
+                .join(sep),
+        ),
+        v => panic!("{} is not an array", v),
+    }
+}
+
+fn str_trim(_state: &mut State, args: &mut [Atom]) -> Atom {
+    Atom::Str(string_arg(&args[0]).trim().to_string())
+}
+
+fn str_upper(_state: &mut State, args: &mut [Atom]) -> Atom {
+    Atom::Str(string_arg(&args[0]).to_uppercase())
+}
+
+fn str_lower(_state: &mut State, args: &mut [Atom]) -> Atom {
+    Atom::Str(string_arg(&args[0]).to_lowercase())
+}
+
+fn str_replace(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let s = string_arg(&args[0]);
+    Atom::Str(s.replace(string_arg(&args[1]), string_arg(&args[2])))
+}
+
+fn str_starts_with(_state: &mut State, args: &mut [Atom]) -> Atom {
+    Atom::Bool(string_arg(&args[0]).starts_with(string_arg(&args[1])))
+}
+
+fn str_ends_with(_state: &mut State, args: &mut [Atom]) -> Atom {
+    Atom::Bool(string_arg(&args[0]).ends_with(string_arg(&args[1])))
+}
+
+fn str_chars(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let s = string_arg(&args[0]);
+    Atom::Array(
+        s.chars()
+            .map(|c| S::Atom(Atom::Str(c.to_string())))
+            .collect(),
+    )
+}
+
+fn str_ord(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let s = string_arg(&args[0]);
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Atom::Int(c as isize),
+        _ => panic!("ord expects a single character but got {:?}", s),
+    }
+}
+
+fn str_chr(_state: &mut State, args: &mut [Atom]) -> Atom {
+    let c = match args[0] {
+        Atom::Int(n) => n.try_into().ok().and_then(std::char::from_u32),
+        _ => None,
+    };
+    match c {
+        Some(c) => Atom::Str(c.to_string()),
+        None => panic!("{} is not a valid code point", args[0]),
+    }
+}
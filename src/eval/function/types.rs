@@ -0,0 +1,73 @@
+use num_bigint::BigInt;
+
+use super::math::{float_to_int, number_arg};
+use crate::Atom;
+use crate::State;
+
+const TYPE_NAMES: [&str; 6] = ["int", "float", "str", "bool", "array", "function"];
+
+pub fn install(state: &mut State) {
+    state.register_fn("type_of", Some(1), |_, args| {
+        Atom::Str(args[0].type_name().to_string())
+    });
+    state.register_fn("int", Some(1), to_int);
+    state.register_fn("float", Some(1), to_float);
+    state.register_fn("str", Some(1), |_, args| Atom::Str(args[0].to_string()));
+    state.register_fn("bool", Some(1), to_bool);
+
+    for ty in TYPE_NAMES {
+        state.register_fn(&format!("is_{}", ty), Some(1), move |_, args| {
+            Atom::Bool(args[0].type_name() == ty)
+        });
+    }
+}
+
+fn conversion_error(v: &Atom, ty: &str) -> ! {
+    match v {
+        Atom::Str(s) => panic!("Can't convert \"{}\" to {}", s, ty),
+        v => panic!("Can't convert {} {} to {}", v.type_name(), v, ty),
+    }
+}
+
+// floats are truncated towards zero
+fn to_int(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        n if n.is_int() => n.clone(),
+        Atom::Float(n) if n.is_finite() => float_to_int(n.trunc()),
+        Atom::Bool(b) => Atom::Int(*b as isize),
+        Atom::Str(s) => match s.trim().parse::<BigInt>() {
+            Ok(n) => Atom::from(n),
+            Err(_) => conversion_error(&args[0], "an int"),
+        },
+        v => conversion_error(v, "an int"),
+    }
+}
+
+fn to_float(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        n if n.is_number() => Atom::Float(number_arg(n)),
+        Atom::Bool(b) => Atom::Float(*b as isize as f64),
+        Atom::Str(s) => match s.trim().parse::<f64>() {
+            Ok(n) => Atom::Float(n),
+            Err(_) => conversion_error(&args[0], "a float"),
+        },
+        v => conversion_error(v, "a float"),
+    }
+}
+
+// numbers are true unless they're zero, and only "true" and
+// "false" convert from strings
+fn to_bool(_state: &mut State, args: &mut [Atom]) -> Atom {
+    match &args[0] {
+        Atom::Bool(b) => Atom::Bool(*b),
+        Atom::Int(n) => Atom::Bool(*n != 0),
+        Atom::BigInt(_) => Atom::Bool(true),
+        Atom::Float(n) => Atom::Bool(*n != 0.0),
+        Atom::Str(s) => match s.trim() {
+            "true" => Atom::Bool(true),
+            "false" => Atom::Bool(false),
+            _ => conversion_error(&args[0], "a bool"),
+        },
+        v => conversion_error(v, "a bool"),
+    }
+}
@@ -0,0 +1,114 @@
+//! Fixes the layout of slang code. Only whitespace is changed, so
+//! comments and the way expressions are written are left alone.
+
+use crate::scanner::{scan_tokens, token::TokenType};
+use crate::{scan_error, SlangError};
+
+const INDENT: &str = "    ";
+
+fn is_open(ty: &TokenType) -> bool {
+    matches!(
+        ty,
+        TokenType::LParen | TokenType::LBrace | TokenType::LBracket
+    )
+}
+
+fn is_close(ty: &TokenType) -> bool {
+    matches!(
+        ty,
+        TokenType::RParen | TokenType::RBrace | TokenType::RBracket
+    )
+}
+
+/// Indents every line by how deeply it's nested in brackets, strips
+/// trailing whitespace and collapses runs of blank lines.
+///
+/// ```
+/// use slang_treewalk::formatter::format_source;
+///
+/// let code = "fn f(x) {\nif (x) {\n  print(x)   \n}\n\n\n}";
+/// let formatted = "fn f(x) {\n    if (x) {\n        print(x)\n    }\n\n}\n";
+/// assert_eq!(format_source(code).unwrap(), formatted);
+/// ```
+pub fn format_source(code: &str) -> Result<String, SlangError> {
+    let tokens = scan_tokens(code);
+    if let Some(e) = scan_error(&tokens) {
+        return Err(e);
+    }
+
+    let lines: Vec<&str> = code.lines().collect();
+    // tokens are numbered from line 1
+    let mut depth_change = vec![0isize; lines.len() + 2];
+    let mut leading_closes = vec![0isize; lines.len() + 2];
+    let mut seen_code = vec![false; lines.len() + 2];
+    // lines that start inside a multiline string are kept as they are,
+    // which are the ones that don't follow a newline token
+    let mut starts_in_code = vec![false; lines.len() + 2];
+    starts_in_code[1] = true;
+
+    for t in &tokens {
+        let line = t.line.min(lines.len() + 1);
+        if t.ty == TokenType::NewLine {
+            starts_in_code[line + 1] = true;
+            continue;
+        }
+
+        if is_open(&t.ty) {
+            depth_change[line] += 1;
+        } else if is_close(&t.ty) {
+            depth_change[line] -= 1;
+            if !seen_code[line] {
+                leading_closes[line] += 1;
+            }
+        }
+        if !is_close(&t.ty) {
+            seen_code[line] = true;
+        }
+    }
+
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut blank_lines = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let n = i + 1;
+        if !starts_in_code[n] {
+            out.push_str(line);
+            out.push('\n');
+        } else if line.trim().is_empty() {
+            blank_lines += 1;
+        } else {
+            if blank_lines > 0 && !out.is_empty() {
+                out.push('\n');
+            }
+            blank_lines = 0;
+
+            let indent = (depth - leading_closes[n]).max(0) as usize;
+            out.push_str(&INDENT.repeat(indent));
+            out.push_str(line.trim());
+            out.push('\n');
+        }
+        depth += depth_change[n];
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use super::*;
+
+    #[test]
+    fn test_format_source() {
+        let code = "\n\nlet a = [\n1,\n  2\n]\nif (a[0] == 1) {\n\tprint(a) # comment  \n} else {\nprint(\"}\")\n}\n\n";
+        let formatted = "let a = [\n    1,\n    2\n]\nif (a[0] == 1) {\n    print(a) # comment\n} else {\n    print(\"}\")\n}\n";
+        assert_eq!(format_source(code).unwrap(), formatted);
+        assert_eq!(format_source(formatted).unwrap(), formatted);
+
+        // multiline strings keep their layout
+        let code = "fn f() {\nlet s = \"\"\"a\n  b [\n\"\"\"\ns\n}";
+        let formatted = "fn f() {\n    let s = \"\"\"a\n  b [\n\"\"\"\n    s\n}\n";
+        assert_eq!(format_source(code).unwrap(), formatted);
+
+        assert!(format_source("let s = \"abc").is_err());
+    }
+}
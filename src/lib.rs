@@ -0,0 +1,478 @@
+//! Slang is a treewalk interpreted scripting language. The [`Interpreter`]
+//! type is the entry point for embedding it in another Rust program.
+
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Once;
+
+pub mod scanner;
+use scanner::token::*;
+use scanner::*;
+
+pub mod parser;
+use parser::*;
+
+pub mod eval;
+pub use eval::atom::Atom;
+
+pub mod statement;
+pub use statement::State;
+
+pub mod block;
+
+pub mod checker;
+
+pub mod module;
+
+pub mod formatter;
+
+pub mod resolver;
+
+pub mod vm;
+
+/// An error from running slang code, split up so that callers can
+/// tell broken code apart from code that fails while it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlangError {
+    /// The code couldn't be scanned or parsed, or the checker found
+    /// errors in it, so none of it ran
+    Syntax(String),
+    /// The code panicked while running
+    Runtime(String),
+    /// The code called `exit()` with this code
+    Exit(i32),
+}
+
+impl std::fmt::Display for SlangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlangError::Syntax(msg) | SlangError::Runtime(msg) => write!(f, "{}", msg),
+            SlangError::Exit(code) => write!(f, "Exited with code {}", code),
+        }
+    }
+}
+
+impl Error for SlangError {}
+
+// scanning doesn't stop at errors, it leaves them in the tokens
+pub(crate) fn scan_error(tokens: &[Token]) -> Option<SlangError> {
+    if let Some(t) = tokens.iter().find(|t| t.ty == TokenType::Unknown) {
+        Some(SlangError::Syntax(format!(
+            "Invalid input {:?} on line {}",
+            t.lexeme, t.line
+        )))
+    } else if let Some(Token {
+        ty: TokenType::Error(msg),
+        line,
+        ..
+    }) = tokens.last()
+    {
+        Some(SlangError::Syntax(format!("{} on line {}", msg, line)))
+    } else {
+        None
+    }
+}
+
+/// Scans, parses and resolves code without running it.
+pub fn parse(code: &str) -> Result<block::Block, SlangError> {
+    let tokens = scan_tokens(code);
+    if let Some(e) = scan_error(&tokens) {
+        return Err(e);
+    }
+
+    // the parser reports errors by panicking too
+    let mut block = catch_quietly(|| parse_block(&mut Lexer::new(tokens)))
+        .map_err(|payload| SlangError::Syntax(panic_message(payload)))?;
+    resolver::resolve(&mut block);
+    Ok(block)
+}
+
+fn check_block(block: &block::Block, state: &State) -> Result<(), SlangError> {
+    checker::check(block, state).map_err(|errors| {
+        let msgs: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        SlangError::Syntax(msgs.join("\n"))
+    })
+}
+
+/// How an [`Interpreter`] runs code. Both give the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Walks the syntax tree
+    TreeWalk,
+    /// Compiles to bytecode and runs it on the stack machine in [`vm`]
+    Vm,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::TreeWalk
+    }
+}
+
+/// Runs code with the backend `state` is set to.
+pub fn run(code: &str, state: &mut State) -> Result<Option<Atom>, SlangError> {
+    run_with(state.backend, code, state)
+}
+
+pub fn run_with(
+    backend: Backend,
+    code: &str,
+    state: &mut State,
+) -> Result<Option<Atom>, SlangError> {
+    let main_block = parse(code)?;
+    check_block(&main_block, state)?;
+    Ok(match backend {
+        Backend::TreeWalk => main_block.execute_unscoped(state),
+        Backend::Vm => vm::execute(&vm::compile(&main_block), state),
+    })
+}
+
+thread_local! {
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+}
+
+// errors are returned, so the panic hook shouldn't also print them.
+// The hook is the process's, so this wraps whatever hook there is
+// once and keeps it quiet only while this thread is catching
+fn catch_quietly<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    static QUIET_HOOK: Once = Once::new();
+    QUIET_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(|c| c.get()) == 0 {
+                hook(info);
+            }
+        }));
+    });
+
+    CATCHING.with(|c| c.set(c.get() + 1));
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(c.get() - 1));
+    res
+}
+
+/// The message a panic was raised with, for callers that catch panics
+/// from slang code themselves.
+pub fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
+
+// the evaluator reports errors by panicking, so this turns those
+// panics into errors and drops any scopes left over from the
+// unwound blocks, which is also how exit() gets out of them
+fn catch_panic<T>(
+    state: &mut State,
+    f: impl FnOnce(&mut State) -> Result<T, SlangError>,
+) -> Result<T, SlangError> {
+    let depth = state.scopes.len();
+    match catch_quietly(|| f(state)) {
+        Ok(res) => res,
+        Err(payload) => {
+            state.scopes.truncate(depth);
+            match payload.downcast::<eval::Exit>() {
+                Ok(exit) => Err(SlangError::Exit(exit.0)),
+                // imports raise syntax errors in the files they import
+                Err(payload) => match payload.downcast::<SlangError>() {
+                    Ok(e) => Err(*e),
+                    Err(payload) => Err(SlangError::Runtime(panic_message(payload))),
+                },
+            }
+        }
+    }
+}
+
+/// A slang interpreter with its own global scope.
+///
+/// ```
+/// use slang_treewalk::{Atom, Interpreter};
+///
+/// let mut interp = Interpreter::new();
+/// interp.set_global("x", 5);
+/// interp.eval_str("fn add(a, b) {\n a + b\n}").unwrap();
+///
+/// let res = interp.call_function("add", vec![Atom::Int(2), 3.into()]).unwrap();
+/// assert_eq!(res, Some(Atom::Int(5)));
+/// assert_eq!(interp.eval_str("add(x, 1)").unwrap(), Some(Atom::Int(6)));
+/// ```
+pub struct Interpreter {
+    state: State,
+}
+
+// a clone gets its own list of imported modules, so importing a file
+// in one doesn't import it in the other. The modules that were
+// already imported are still shared
+impl Clone for Interpreter {
+    fn clone(&self) -> Self {
+        let mut state = self.state.clone();
+        state.modules = Rc::new(RefCell::new(self.state.modules.borrow().clone()));
+        Interpreter { state }
+    }
+}
+
+/// Slang code that [`Interpreter::new`] runs before anything else,
+/// defining helpers like `range`.
+pub const PRELUDE: &str = include_str!("prelude.slang");
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the builtins and the functions from the
+    /// [`PRELUDE`].
+    pub fn new() -> Self {
+        let mut interp = Interpreter::without_prelude();
+        if let Err(e) = interp.eval_str(PRELUDE) {
+            panic!("Error in the prelude: {}", e);
+        }
+        // imported modules get the prelude too
+        let globals = interp.state.scopes[0].clone();
+        interp.state.modules.borrow_mut().base = globals;
+        interp
+    }
+
+    /// An interpreter with only the builtins that are implemented in
+    /// Rust.
+    pub fn without_prelude() -> Self {
+        Interpreter {
+            state: State::default(),
+        }
+    }
+
+    /// Runs code in the global scope and returns the value of
+    /// the last statement, if it has one.
+    pub fn eval_str(&mut self, code: &str) -> Result<Option<Atom>, Box<dyn Error>> {
+        Ok(catch_panic(&mut self.state, |state| run(code, state))?)
+    }
+
+    /// Chooses how code run from now on is run, which is the
+    /// tree-walker unless this is called.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.state.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.state.backend
+    }
+
+    /// Parses and checks code against the globals that are defined,
+    /// without running it.
+    pub fn check(&self, code: &str) -> Result<(), SlangError> {
+        check_block(&parse(code)?, &self.state)
+    }
+
+    pub fn eval_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Option<Atom>, Box<dyn Error>> {
+        let file = std::fs::read_to_string(&path)?;
+        self.eval_str_in(&file, path)
+    }
+
+    /// Runs code as if it was in the file at `path`, which is where
+    /// its imports are looked for.
+    pub fn eval_str_in(
+        &mut self,
+        code: &str,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Option<Atom>, Box<dyn Error>> {
+        let path = path.as_ref();
+        let old_path = self.state.path.replace(path.to_path_buf());
+        self.state.modules.borrow_mut().enter(path);
+
+        let res = self.eval_str(code);
+
+        self.state.modules.borrow_mut().leave(path);
+        self.state.path = old_path;
+        res
+    }
+
+    pub fn set_global(&mut self, name: &str, val: impl Into<Atom>) {
+        self.state.scopes[0]
+            .vars
+            .insert(name.to_string(), val.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Atom> {
+        self.state.scopes[0].vars.get(name)
+    }
+
+    /// Calls a global script or native function with already
+    /// evaluated arguments.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        args: Vec<Atom>,
+    ) -> Result<Option<Atom>, Box<dyn Error>> {
+        let f = match self.get_global(name) {
+            Some(f @ Atom::Function(_)) | Some(f @ Atom::NativeFunction(_)) => f.clone(),
+            Some(v) => return Err(format!("{} is not a function", v).into()),
+            None => return Err(format!("Function {} is undefined", name).into()),
+        };
+
+        Ok(catch_panic(&mut self.state, |state| {
+            Ok(eval::call_value(&f, args, state))
+        })?)
+    }
+
+    /// Makes a Rust function callable from scripts. If `arity` is
+    /// `None` the function accepts any number of arguments.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        func: impl Fn(&mut State, &mut [Atom]) -> Atom + 'static,
+    ) {
+        self.state.register_fn(name, arity, func);
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_interpreter() {
+        let mut interp = Interpreter::new();
+        interp.set_global("x", 5);
+        interp.register_fn("triple", Some(1), |_, args| args[0].clone() * Atom::Int(3));
+        interp
+            .eval_str("fn add(a, b) {\n a + b\n}\nlet y = triple(x)")
+            .unwrap();
+
+        assert_eq!(interp.get_global("y"), Some(&Atom::Int(15)));
+
+        let res = interp
+            .call_function("add", vec![1.5.into(), "y".into()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(res, Atom::Str("1.5y".to_string()));
+
+        let arr: Vec<isize> = interp
+            .eval_str("[1, 2, x]")
+            .unwrap()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(arr, vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn test_interpreter_errors() {
+        let mut interp = Interpreter::new();
+        assert!(interp.eval_str("{\n undefined_var\n}").is_err());
+        assert_eq!(interp.state().scopes.len(), 1);
+        assert!(interp.call_function("undefined_fn", vec![]).is_err());
+        assert_eq!(interp.eval_str("1 + 1").unwrap(), Some(Atom::Int(2)));
+    }
+
+    #[test]
+    fn test_prelude() {
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.eval_str("mul(6, 7)").unwrap(), Some(Atom::Int(42)));
+        let arr: Vec<isize> = interp
+            .eval_str("range(2, 5)")
+            .unwrap()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(arr, vec![2, 3, 4]);
+
+        let mut bare = Interpreter::without_prelude();
+        assert_eq!(bare.get_global("range"), None);
+        assert!(bare.eval_str("mul(6, 7)").is_err());
+        assert!(bare.get_global("len").is_some());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut interp = Interpreter::new();
+        let kind = |res: Result<Option<Atom>, Box<dyn Error>>| {
+            res.unwrap_err()
+                .downcast::<SlangError>()
+                .map(|e| *e)
+                .unwrap()
+        };
+
+        assert!(matches!(
+            kind(interp.eval_str("let x = (1")),
+            SlangError::Syntax(_)
+        ));
+        assert!(matches!(
+            kind(interp.eval_str("let s = \"abc")),
+            SlangError::Syntax(_)
+        ));
+        assert!(matches!(
+            kind(interp.eval_str("1 + \"a\" * 2.5")),
+            SlangError::Syntax(_)
+        ));
+        assert!(matches!(
+            kind(interp.eval_str("1 // 0")),
+            SlangError::Runtime(_)
+        ));
+        assert!(matches!(
+            kind(interp.eval_str("undefined_var")),
+            SlangError::Runtime(_)
+        ));
+
+        assert!(interp.check("let x = 1\nx + 1").is_ok());
+        assert!(interp.check("let x: int = \"a\"").is_err());
+        assert_eq!(interp.get_global("x"), None);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut interp = Interpreter::new();
+        let code = "let x = 1\nfn f(n) {\n while (true) {\n let y = [exit(n)]\n }\n}\nf(3)\nx = 2";
+        let err = interp.eval_str(code).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&SlangError::Exit(3)));
+        assert_eq!(interp.state().scopes.len(), 1);
+        assert_eq!(interp.get_global("x"), Some(&Atom::Int(1)));
+
+        let err = interp.call_function("exit", vec![]).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&SlangError::Exit(0)));
+        let err = interp.eval_str("exit(\"a\")").unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(SlangError::Runtime(_))));
+
+        let err = interp.eval_str("exit(255)").unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&SlangError::Exit(255)));
+        for code in ["256", "-1", "100000000000000000000"] {
+            let err = interp.eval_str(&format!("exit({})", code)).unwrap_err();
+            let msg = format!("Exit code {} has to be from 0 to 255", code);
+            assert_eq!(err.downcast_ref(), Some(&SlangError::Runtime(msg)));
+        }
+    }
+
+    #[test]
+    fn test_clone() {
+        let path = "test_files/modules.slang";
+        let start = Interpreter::new();
+        let mut interp = start.clone();
+        let first = interp.eval_file(path).unwrap();
+        assert!(first.is_some());
+
+        // the modules imported above aren't imported in the copy
+        let mut copy = start.clone();
+        assert_eq!(copy.eval_file(path).unwrap(), first);
+        assert_ne!(copy.get_global("geometry"), interp.get_global("geometry"));
+    }
+}
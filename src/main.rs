@@ -1,60 +1,86 @@
-#![feature(or_patterns)]
-#![feature(type_alias_impl_trait)]
-#![feature(bindings_after_at)]
-
-use crate::eval::atom::Atom;
 use std::error::Error;
-use std::io;
-use std::io::BufRead;
-use std::io::Write;
+use std::fmt;
+use std::io::{self, Read};
+
+use slang_treewalk::formatter::format_source;
+use slang_treewalk::scanner::scan_tokens;
+use slang_treewalk::{panic_message, Atom, Backend, Interpreter, SlangError};
+
+mod bench;
+mod repl;
 
-mod scanner;
-use scanner::token::*;
-use scanner::*;
+const USAGE: &str = "\
+Usage: slang-treewalk [--no-prelude] [--vm] [command]
 
-mod parser;
-use parser::*;
+Commands:
+  repl                  start the REPL, which is what happens with no command
+  run <file> [args...]  run a script, which can read args with args()
+  <file> [args...]      the same as run
+  -e <code> [args...]   run code and print its value
+  check <file>          look for errors in a script without running it
+  fmt <file>            print a script with its indentation fixed
+  tokens <file>         print the tokens a script is scanned into
+  ast <file>            print the statements a script is parsed into
+  bench [name...]       time the benchmarks in test_files, see below
+  help                  show this message
 
-mod eval;
-use statement::State;
+A <file> of - reads the script from stdin, --no-prelude starts without the
+functions from the prelude, like range(), and --vm compiles scripts to
+bytecode instead of walking their syntax trees.
 
-mod statement;
+bench runs each benchmark with both backends, and with python3 if it has a
+.py version, and compares them against bench_baseline.json. It fails if
+one got more than 10% slower or allocates more. --save writes the results
+to the baseline instead, --baseline <file> uses another baseline,
+--runs <n> keeps the fastest of n runs and --dir <dir> reads the
+benchmarks from dir instead of ./test_files.";
 
-mod block;
+// the same codes as sysexits.h
+const EXIT_USAGE: i32 = 64;
+const EXIT_SYNTAX: i32 = 65;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_RUNTIME: i32 = 70;
+
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n\n{}", self.0, USAGE)
+    }
+}
+
+impl Error for UsageError {}
+
+fn exit_code(e: &(dyn Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<SlangError>() {
+        match e {
+            SlangError::Syntax(_) => EXIT_SYNTAX,
+            SlangError::Runtime(_) => EXIT_RUNTIME,
+            SlangError::Exit(code) => *code,
+        }
+    } else if e.is::<UsageError>() {
+        EXIT_USAGE
+    } else if e.is::<io::Error>() {
+        EXIT_NO_INPUT
+    } else {
+        1
+    }
+}
 
-fn run(code: &str, state: &mut State, unscoped: bool) -> Result<Option<Atom>, Box<dyn Error>> {
-    let tokens = scan_tokens(code);
-    if let Some(t) = tokens.iter().find(|t| t.ty == TokenType::Unknown) {
-        Err(format!("Invalid {:?} ({}) on line {}", t.ty, t.lexeme, t.line).into())
+fn read_source(path: &str) -> Result<String, Box<dyn Error>> {
+    if path == "-" {
+        let mut code = String::new();
+        io::stdin().read_to_string(&mut code)?;
+        Ok(code)
     } else {
-        // println!(
-        //     "{:?}",
-        //     tokens
-        //         .clone()
-        //         .iter()
-        //         .map(|t| t.ty.clone())
-        //         .collect::<Vec<TokenType>>()
-        // );
-        let mut lexer = Lexer::new(tokens);
-
-        let mut main_block = parse_block(&mut lexer);
-
-        let res = if unscoped {
-            main_block.execute_unscoped(state)
-        } else {
-            main_block.execute_unscoped(state)
-        };
-
-        Ok(res)
+        std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't read {}: {}", path, e)).into())
     }
 }
 
-fn run_file(
-    path: impl AsRef<std::path::Path> + std::fmt::Debug + std::clone::Clone,
-    state: &mut State,
-) -> Result<Option<Atom>, Box<dyn Error>> {
-    let file = std::fs::read_to_string(path)?;
-    let res = run(&file, state, false)?;
+fn run_code(code: &str, interp: &mut Interpreter) -> Result<Option<Atom>, Box<dyn Error>> {
+    let res = interp.eval_str(code)?;
     if let Some(ref a) = res {
         println!("{}", a);
     }
@@ -62,68 +88,169 @@ fn run_file(
     Ok(res)
 }
 
-fn run_prompt(state: &mut State) -> Result<Option<Atom>, Box<dyn Error>> {
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-    let mut buffer = Vec::new();
+// imports are relative to the file, or the current directory for stdin
+fn run_file(path: &str, interp: &mut Interpreter) -> Result<Option<Atom>, Box<dyn Error>> {
+    let code = read_source(path)?;
+    if path == "-" {
+        return run_code(&code, interp);
+    }
 
-    loop {
-        print!("Slang |>\n");
-        stdout.flush()?;
-        buffer.clear();
+    let res = interp.eval_str_in(&code, path)?;
+    if let Some(ref a) = res {
+        println!("{}", a);
+    }
 
-        // stupid hack, REPL goes until ~
-        stdin.lock().read_until(b'~', &mut buffer)?;
+    Ok(res)
+}
 
-        let buffer_str = std::str::from_utf8(&buffer).unwrap();
+// the commands that take a single file and nothing else
+fn file_arg<'a>(command: &str, args: &'a [String]) -> Result<&'a str, UsageError> {
+    match args {
+        [file] => Ok(file),
+        [] => Err(UsageError(format!("{} needs a file", command))),
+        _ => Err(UsageError(format!("{} only takes a file", command))),
+    }
+}
 
-        if buffer_str == "exit" {
-            break;
+fn cli(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut prelude = true;
+    let mut backend = Backend::TreeWalk;
+    let mut args = args;
+    while let Some((flag, rest)) = args.split_first() {
+        match flag.as_str() {
+            "--no-prelude" => prelude = false,
+            "--vm" => backend = Backend::Vm,
+            _ => break,
         }
+        args = rest;
+    }
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => ("repl", &[][..]),
+    };
 
-        if let Some(a) = run(&buffer_str[..buffer.len() - 2], state, true)? {
-            println!("{}", a);
+    // the arguments scripts can see through args()
+    let script_args = match command {
+        "run" | "-e" => rest.get(1..).unwrap_or_default(),
+        "repl" | "check" | "fmt" | "tokens" | "ast" | "bench" | "help" | "-h" | "--help" => &[][..],
+        _ => rest,
+    };
+    let script_args: Vec<String> = script_args.to_vec();
+
+    let mut interp = if prelude {
+        Interpreter::new()
+    } else {
+        Interpreter::without_prelude()
+    };
+    interp.set_backend(backend);
+    interp.register_fn("args", Some(0), move |_, _| Atom::from(script_args.clone()));
+
+    match command {
+        "repl" if rest.is_empty() => repl::run(&mut interp),
+        "run" => match rest.first() {
+            Some(file) => run_file(file, &mut interp).map(|_| ()),
+            None => Err(UsageError("run needs a file".to_string()).into()),
+        },
+        "-e" => match rest.first() {
+            Some(code) => run_code(code, &mut interp).map(|_| ()),
+            None => Err(UsageError("-e needs some code".to_string()).into()),
+        },
+        "check" => {
+            interp.check(&read_source(file_arg(command, rest)?)?)?;
+            Ok(())
+        }
+        "fmt" => {
+            print!(
+                "{}",
+                format_source(&read_source(file_arg(command, rest)?)?)?
+            );
+            Ok(())
+        }
+        "tokens" => {
+            for t in scan_tokens(&read_source(file_arg(command, rest)?)?) {
+                println!("{:>4} {}", t.line, t.to_string().trim_end());
+            }
+            Ok(())
+        }
+        "ast" => {
+            println!(
+                "{}",
+                slang_treewalk::parse(&read_source(file_arg(command, rest)?)?)?
+            );
+            Ok(())
         }
+        "bench" => bench::run(rest),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        "repl" => Err(UsageError("repl doesn't take any arguments".to_string()).into()),
+        file if file.starts_with('-') && file != "-" => {
+            Err(UsageError(format!("Unknown option {}", file)).into())
+        }
+        file => run_file(file, &mut interp).map(|_| ()),
     }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // errors are printed below, so the default panic message and
+    // backtrace would just be noise
+    std::panic::set_hook(Box::new(|_| {}));
 
-    // TODO: make this return the last expr
-    Ok(None)
+    // anything that still panics is a bug, but it shouldn't exit
+    // without saying anything
+    let res = match std::panic::catch_unwind(|| cli(&args)) {
+        Ok(res) => res,
+        Err(payload) => {
+            eprintln!("Error: {}", panic_message(payload));
+            std::process::exit(EXIT_RUNTIME);
+        }
+    };
+
+    if let Err(e) = res {
+        // exit() isn't really an error, the script asked for it
+        if !matches!(e.downcast_ref(), Some(SlangError::Exit(_))) {
+            eprintln!("Error: {}", e);
+        }
+        std::process::exit(exit_code(&*e));
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = std::env::args().collect::<Vec<String>>();
-
-    let mut top_state = State::default();
-
-    {
-        use crate::block::Block;
-        use crate::eval::atom::FunctionData;
-        use crate::statement::{Declaration, Stmt};
-        top_state.declare(Declaration {
-            lhs: "mul".to_string(),
-            rhs: S::Atom(Atom::Function(FunctionData {
-                arg_names: vec!["x".to_string(), "y".to_string()],
-                fn_block: Block::new(vec![Stmt::ExprStmt(S::Cons(
-                    Op::Multiply,
-                    vec![
-                        S::Atom(Atom::Identifier("x".to_string())),
-                        S::Atom(Atom::Identifier("y".to_string())),
-                    ],
-                ))]),
-            })),
-            alias: true,
-            plus_or_minus: None,
-        });
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let mut interp = Interpreter::new();
+        let code = |res: Result<Option<Atom>, Box<dyn Error>>| exit_code(&*res.unwrap_err());
+
+        assert_eq!(code(interp.eval_str("let x = [1")), EXIT_SYNTAX);
+        assert_eq!(code(interp.eval_str("1 + true")), EXIT_SYNTAX);
+        assert_eq!(code(interp.eval_str("[1][5]")), EXIT_RUNTIME);
+        assert_eq!(code(interp.eval_file("missing.slang")), EXIT_NO_INPUT);
+        assert_eq!(code(interp.eval_str("exit(3)")), 3);
+
+        let usage = cli(&["fmt".to_string()]).unwrap_err();
+        assert_eq!(exit_code(&*usage), EXIT_USAGE);
     }
 
-    match args.len() {
-        0 | 1 => run_prompt(&mut top_state),
-        2 => run_file(args[1].clone(), &mut top_state),
-        _ => {
-            println!("Usage: rlox [script]");
-            Err("bad input".into())
-        }
-    }?;
+    #[test]
+    fn test_invalid_character() {
+        let path = std::env::temp_dir().join("slang_cli_invalid_character.slang");
+        std::fs::write(&path, "let x = 1\nx @ 2\n").unwrap();
+        let path = path.to_string_lossy().to_string();
 
-    Ok(())
+        for command in ["run", "check", "fmt", "ast"] {
+            let err = cli(&[command.to_string(), path.clone()]).unwrap_err();
+            assert_eq!(err.to_string(), "Invalid input \"@\" on line 2");
+            assert_eq!(exit_code(&*err), EXIT_SYNTAX, "with {}", command);
+        }
+        let err = cli(&["--vm".to_string(), "run".to_string(), path.clone()]).unwrap_err();
+        assert_eq!(exit_code(&*err), EXIT_SYNTAX);
+        // tokens lists the Unknown token instead
+        assert!(cli(&["tokens".to_string(), path]).is_ok());
+    }
 }
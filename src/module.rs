@@ -0,0 +1,310 @@
+//! Files imported with `import`. Each one runs once, in its own global
+//! scope, and the globals it defines can then be used through it like
+//! `geometry.area(2, 3)`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::eval::atom::{Atom, FunctionCall};
+use crate::eval::{call_value, eval_expr, Exit};
+use crate::parser::S;
+use crate::statement::{Import, Scope, State};
+use crate::SlangError;
+
+/// A file that has been imported. Its state is kept so that its
+/// functions can still see its globals when they're called from
+/// another file.
+#[derive(Clone)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub state: Rc<RefCell<State>>,
+    /// The globals it defines, which leaves out the builtins
+    pub exports: Rc<Vec<String>>,
+}
+
+// the state is left out since it can lead back to this module
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Module({}, {})", self.name, self.path.display())
+    }
+}
+
+/// What every file run by an interpreter shares.
+#[derive(Debug, Default, Clone)]
+pub struct Modules {
+    /// The globals a module starts with, which are the builtins and
+    /// the prelude
+    pub base: Scope,
+    loaded: HashMap<PathBuf, Module>,
+    /// The files being run right now, innermost last, which is how
+    /// import cycles are found
+    loading: Vec<PathBuf>,
+}
+
+impl Modules {
+    /// Marks a file as being run, so that importing it from one of
+    /// the files it imports is an error.
+    pub fn enter(&mut self, path: &Path) {
+        if let Ok(path) = path.canonicalize() {
+            self.loading.push(path);
+        }
+    }
+
+    pub fn leave(&mut self, path: &Path) {
+        if let Ok(path) = path.canonicalize() {
+            if let Some(i) = self.loading.iter().rposition(|p| *p == path) {
+                self.loading.remove(i);
+            }
+        }
+    }
+
+    /// Forgets the modules that have been loaded, so importing them
+    /// again runs them again.
+    pub fn clear(&mut self) {
+        self.loaded.clear();
+        self.loading.clear();
+    }
+}
+
+impl Module {
+    fn get(&self, name: &str) -> Atom {
+        if !self.exports.iter().any(|e| e == name) {
+            panic!("Module {} has no {}", self.name, name);
+        }
+        self.state.borrow().scopes[0]
+            .vars
+            .get(name)
+            .unwrap()
+            .clone()
+    }
+
+    /// Evaluates `module.name` or `module.name(args)`. Functions run
+    /// in the module's state, with arguments evaluated in the caller's.
+    pub fn access(&self, rhs: &S, state: &mut State) -> Atom {
+        match rhs {
+            S::Atom(Atom::Identifier(name)) => self.get(name),
+            S::Atom(Atom::FnCall(FunctionCall { name, args })) => {
+                let f = self.get(name);
+                let args = args.iter().map(|a| eval_expr(a, state)).collect();
+                let mut module_state = match self.state.try_borrow_mut() {
+                    Ok(s) => s,
+                    Err(_) => panic!(
+                        "Can't call {}.{} while one of {}'s functions is running",
+                        self.name, name, self.name
+                    ),
+                };
+                match call_value(&f, args, &mut module_state) {
+                    Some(a) => a,
+                    None => panic!("Function {}.{} didn't return a value", self.name, name),
+                }
+            }
+            _ => panic!("Can't access {} of module {}", rhs, self.name),
+        }
+    }
+}
+
+/// The name `import "path"` gives a module, which is its file name
+/// without the extension. That has to be a valid variable name.
+pub fn default_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    if stem.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !stem.starts_with(|c: char| c.is_numeric())
+    {
+        Some(stem.to_string())
+    } else {
+        None
+    }
+}
+
+/// Imports a module into the innermost scope.
+pub fn declare_import(import: &Import, state: &mut State) {
+    let module = self::import(import, state);
+    let name = module.name.clone();
+    match import.slot {
+        Some(slot) => state.declare_local(&name, slot.index, Atom::Module(module), None),
+        None => state.declare_value(&name, Atom::Module(module), None),
+    }
+}
+
+/// Runs the file an import refers to, unless it has been already,
+/// and gives back the module it defines. Paths are relative to the
+/// file doing the importing.
+pub fn import(import: &Import, state: &State) -> Module {
+    let dir = state
+        .path
+        .as_ref()
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let path = match dir.join(&import.path).canonicalize() {
+        Ok(path) => path,
+        Err(e) => panic!("Can't import {}: {}", import.path, e),
+    };
+    let name = match &import.name {
+        Some(name) => name.clone(),
+        None => match default_name(&path) {
+            Some(name) => name,
+            None => panic!(
+                "{} isn't a valid name, use import name from \"{}\" instead",
+                path.display(),
+                import.path
+            ),
+        },
+    };
+
+    let mut modules = state.modules.borrow_mut();
+    if let Some(module) = modules.loaded.get(&path) {
+        return Module {
+            name,
+            ..module.clone()
+        };
+    }
+    if let Some(i) = modules.loading.iter().position(|p| *p == path) {
+        let cycle: Vec<String> = modules.loading[i..]
+            .iter()
+            .chain(Some(&path))
+            .map(|p| p.display().to_string())
+            .collect();
+        panic!("Import cycle: {}", cycle.join(" -> "));
+    }
+
+    let code = match std::fs::read_to_string(&path) {
+        Ok(code) => code,
+        Err(e) => panic!("Can't import {}: {}", import.path, e),
+    };
+    modules.loading.push(path.clone());
+    let base = modules.base.clone();
+    // running the module can import more modules
+    drop(modules);
+
+    let mut module_state = State {
+        scopes: vec![base],
+        path: Some(path.clone()),
+        modules: state.modules.clone(),
+        steps: None,
+        quiet: state.quiet,
+        backend: state.backend,
+    };
+    let res = panic::catch_unwind(AssertUnwindSafe(|| crate::run(&code, &mut module_state)));
+    let mut modules = state.modules.borrow_mut();
+    modules.loading.pop();
+
+    match res {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => module_error(e, &path),
+        Err(payload) if payload.is::<Exit>() => panic::resume_unwind(payload),
+        // a syntax error from a module this one imported
+        Err(payload) => match payload.downcast::<SlangError>() {
+            Ok(e) => module_error(*e, &path),
+            Err(payload) => panic!("{}\n  in {}", crate::panic_message(payload), path.display()),
+        },
+    }
+
+    let exports = module_state.scopes[0]
+        .vars
+        .keys()
+        .filter(|name| !modules.base.vars.contains_key(name))
+        .cloned()
+        .collect();
+    let module = Module {
+        name,
+        path: path.clone(),
+        state: Rc::new(RefCell::new(module_state)),
+        exports: Rc::new(exports),
+    };
+    modules.loaded.insert(path, module.clone());
+    module
+}
+
+// adds the module to the error, keeping syntax errors as syntax errors
+// so they aren't reported as something that went wrong while running
+fn module_error(e: SlangError, path: &Path) -> ! {
+    match e {
+        SlangError::Syntax(msg) => panic::resume_unwind(Box::new(SlangError::Syntax(format!(
+            "{}\n  in {}",
+            msg,
+            path.display()
+        )))),
+        e => panic!("{}\n  in {}", e, path.display()),
+    }
+}
+
+#[cfg(test)]
+mod module_tests {
+    use super::*;
+    use crate::Interpreter;
+
+    #[test]
+    fn test_default_name() {
+        assert_eq!(
+            default_name(Path::new("lib/geometry.slang")),
+            Some("geometry".to_string())
+        );
+        assert_eq!(default_name(Path::new("my-lib.slang")), None);
+        assert_eq!(default_name(Path::new("2d.slang")), None);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let mut interp = Interpreter::new();
+        let err = interp
+            .eval_file("test_files/import_cycle.slang")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Import cycle: "));
+        assert!(err.ends_with("cycle_a.slang"));
+
+        assert!(interp.eval_str("import \"missing.slang\"").is_err());
+        assert!(interp
+            .eval_str("import g from \"test_files/lib/geometry.slang\"\ng.nope")
+            .is_err());
+
+        // a syntax error in an imported file is still a syntax error,
+        // with the files that imported it
+        let dir = std::env::temp_dir().join("slang_import_syntax");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("bad.slang"), "let x = (1").unwrap();
+        std::fs::write(dir.join("outer.slang"), "import \"bad.slang\"").unwrap();
+        let outer = dir.join("outer.slang");
+        let err = interp
+            .eval_str(&format!("import {:?}", outer.display().to_string()))
+            .unwrap_err();
+        match err.downcast_ref() {
+            Some(SlangError::Syntax(msg)) => {
+                assert!(msg.contains("bad.slang\n  in "), "{}", msg);
+                assert!(msg.ends_with("outer.slang"), "{}", msg);
+            }
+            e => panic!("expected a syntax error, not {:?}", e),
+        }
+
+        // nothing is left marked as loading after an error
+        let res =
+            interp.eval_str("import g from \"test_files/lib/geometry.slang\"\ng.square_area(3)");
+        assert_eq!(res.unwrap(), Some(Atom::Int(9)));
+    }
+
+    #[test]
+    fn test_vm_modules() {
+        // modules run on the backend of the code importing them
+        let mut interp = Interpreter::new();
+        interp.set_backend(crate::Backend::Vm);
+        let res =
+            interp.eval_str("import g from \"test_files/lib/geometry.slang\"\ng.square_area(3)");
+        assert_eq!(res.unwrap(), Some(Atom::Int(9)));
+
+        let m = match interp.get_global("g") {
+            Some(Atom::Module(m)) => m.clone(),
+            v => panic!("g is {:?}", v),
+        };
+        assert_eq!(m.state.borrow().backend, crate::Backend::Vm);
+        match m.get("square_area") {
+            Atom::Function(f) => assert!(f.code.borrow().is_some()),
+            v => panic!("square_area is {:?}", v),
+        }
+    }
+}
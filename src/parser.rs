@@ -1,10 +1,11 @@
 use crate::eval::atom::FunctionCall;
 use crate::statement::Declaration;
 use std::fmt;
+use std::rc::Rc;
 
 use crate::{scanner::token::*, statement::Stmt};
 
-use crate::eval::atom::Atom;
+use crate::eval::atom::{Atom, FormatSpec};
 
 use crate::block::Block;
 
@@ -13,6 +14,9 @@ mod fn_parse;
 mod for_parse;
 mod ident_parse;
 mod if_parse;
+mod import_parse;
+mod interp_parse;
+mod type_parse;
 mod while_parse;
 
 // https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html
@@ -32,6 +36,13 @@ pub enum Op {
     Negate,
     Multiply,
     Divide,
+    FloorDivide,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
     Less,
     Equal,
     Greater,
@@ -41,6 +52,8 @@ pub enum Op {
     Or,
     Indexing,
     Access,
+    Format(FormatSpec),
+    Slice,
 }
 
 impl fmt::Display for Op {
@@ -56,6 +69,13 @@ impl fmt::Display for Op {
                 Op::MinusAssign => "-=",
                 Op::Multiply => "*",
                 Op::Divide => "/",
+                Op::FloorDivide => "//",
+                Op::Power => "**",
+                Op::BitAnd => "&",
+                Op::BitOr => "|",
+                Op::BitXor => "^",
+                Op::ShiftLeft => "<<",
+                Op::ShiftRight => ">>",
                 Op::Less => "<",
                 Op::Equal => "==",
                 Op::Greater => ">",
@@ -65,6 +85,8 @@ impl fmt::Display for Op {
                 Op::Or => "||",
                 Op::Indexing => "[]",
                 Op::Access => "access",
+                Op::Format(_) => "format",
+                Op::Slice => "[:]",
             }
         )
     }
@@ -119,15 +141,30 @@ impl Lexer {
 }
 
 pub fn parse_block(lexer: &mut Lexer) -> Block {
-    let add_stmt = || {
+    let mut statements = Vec::new();
+    let mut lines = Vec::new();
+
+    loop {
+        // skipped here rather than in parse_stmt so that the line
+        // is the one the statement starts on
+        while matches!(lexer.peek().ty, TokenType::NewLine | TokenType::Semicolon) {
+            lexer.next();
+        }
         if lexer.is_empty() {
-            None
-        } else {
-            parse_stmt(lexer)
+            break;
         }
-    };
 
-    Block::new(std::iter::from_fn(add_stmt).collect())
+        let line = lexer.peek().line;
+        match parse_stmt(lexer) {
+            Some(stmt) => {
+                statements.push(stmt);
+                lines.push(line);
+            }
+            None => break,
+        }
+    }
+
+    Block::with_lines(statements, lines)
 }
 
 pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
@@ -193,6 +230,10 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
             lexer.next();
             Some(Stmt::Break)
         }
+        Token {
+            ty: TokenType::Import,
+            ..
+        } => Some(Stmt::Import(import_parse::parse_import(lexer))),
         Token {
             ty: TokenType::Function,
             ..
@@ -200,9 +241,11 @@ pub fn parse_stmt(lexer: &mut Lexer) -> Option<Stmt> {
             let (fn_name, fn_data) = fn_parse::parse_fn_dec(lexer);
             Some(Stmt::Dec(Declaration {
                 lhs: fn_name,
-                rhs: S::Atom(Atom::Function(fn_data)),
+                rhs: S::Atom(Atom::Function(Rc::new(fn_data))),
                 alias: true,
                 plus_or_minus: None,
+                ty: None,
+                slot: None,
             }))
         }
         _t => Some(Stmt::ExprStmt(parse_expr(lexer))),
@@ -217,10 +260,13 @@ fn is_prefix_op(t: &TokenType) -> bool {
     matches!(t, TokenType::Minus | TokenType::Bang)
 }
 
+// paren_depth is only passed along until unbalanced parens are reported
+#[allow(clippy::only_used_in_recursion)]
 fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
     let nx = lexer.next();
     let mut lhs = match nx.ty {
         TokenType::Literal(a) => S::Atom(a),
+        TokenType::StrInterp(parts) => interp_parse::parse_interpolation(parts),
         TokenType::True => S::Atom(Atom::Bool(true)),
         TokenType::False => S::Atom(Atom::Bool(false)),
         TokenType::Identifier => match lexer.peek().ty {
@@ -278,6 +324,7 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
             | TokenType::NewLine
             | TokenType::Semicolon
             | TokenType::Comma
+            | TokenType::Colon
             | TokenType::RBracket => {
                 break;
             }
@@ -287,6 +334,13 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
             TokenType::MinusAssign => Op::MinusAssign,
             TokenType::Slash => Op::Divide,
             TokenType::Star => Op::Multiply,
+            TokenType::SlashSlash => Op::FloorDivide,
+            TokenType::StarStar => Op::Power,
+            TokenType::Ampersand => Op::BitAnd,
+            TokenType::Pipe => Op::BitOr,
+            TokenType::Caret => Op::BitXor,
+            TokenType::LessLess => Op::ShiftLeft,
+            TokenType::GreaterGreater => Op::ShiftRight,
             TokenType::Bang => Op::Negate,
             TokenType::Equal => Op::Equal,
             TokenType::Less => Op::Less,
@@ -313,9 +367,29 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
             lexer.next();
 
             lhs = if op == Op::Indexing {
-                let rhs = parse_expr(lexer);
-                assert_eq!(lexer.next().ty, TokenType::RBracket);
-                S::Cons(op, vec![lhs, rhs])
+                // a[b] is indexing, a[b:c], a[b:] and a[:c] are slices,
+                // with (Slice a b) meaning until the end
+                let rhs = if lexer.peek().ty == TokenType::Colon {
+                    S::Atom(Atom::Int(0))
+                } else {
+                    parse_expr(lexer)
+                };
+
+                if lexer.peek().ty == TokenType::Colon {
+                    lexer.next();
+                    let end = if lexer.peek().ty == TokenType::RBracket {
+                        None
+                    } else {
+                        Some(parse_expr(lexer))
+                    };
+                    assert_eq!(lexer.next().ty, TokenType::RBracket);
+                    let mut xs = vec![lhs, rhs];
+                    xs.extend(end);
+                    S::Cons(Op::Slice, xs)
+                } else {
+                    assert_eq!(lexer.next().ty, TokenType::RBracket);
+                    S::Cons(op, vec![lhs, rhs])
+                }
             } else {
                 S::Cons(op, vec![lhs])
             };
@@ -339,7 +413,7 @@ fn expr_bp(lexer: &mut Lexer, bp: u8, paren_depth: u16) -> S {
 
 fn postfix_binding_power(op: &Op) -> Option<(u8, ())> {
     let res = match op {
-        Op::Indexing => (9, ()),
+        Op::Indexing => (19, ()),
         _ => return None,
     };
     Some(res)
@@ -347,10 +421,15 @@ fn postfix_binding_power(op: &Op) -> Option<(u8, ())> {
 
 fn infix_binding_power(op: &Op) -> (u8, u8) {
     match op {
-        Op::Access => (8, 9),
-        Op::Plus | Op::Minus => (4, 5),
-        Op::Multiply | Op::Divide => (6, 7),
-        Op::Mod => (2, 3),
+        Op::Access => (18, 19),
+        // right associative, and binds tighter than a prefix minus
+        Op::Power => (16, 15),
+        Op::Multiply | Op::Divide | Op::FloorDivide | Op::Mod => (13, 14),
+        Op::Plus | Op::Minus => (11, 12),
+        Op::ShiftLeft | Op::ShiftRight => (9, 10),
+        Op::BitAnd => (7, 8),
+        Op::BitXor => (5, 6),
+        Op::BitOr => (3, 4),
         Op::And | Op::Or => (1, 2),
         Op::Equal | Op::NotEqual | Op::Less | Op::Greater => (0, 1),
         _ => panic!("bad op {:?}", op),
@@ -359,8 +438,8 @@ fn infix_binding_power(op: &Op) -> (u8, u8) {
 
 fn prefix_binding_power(op: &Op) -> ((), u8) {
     match op {
-        Op::Minus => ((), 9),
-        Op::Negate => ((), 10),
+        Op::Minus => ((), 15),
+        Op::Negate => ((), 20),
         _ => panic!("bad op: {:?}", op),
     }
 }
@@ -392,8 +471,17 @@ mod parser_tests {
             "5 + 5" => "(+ 5 5)",
             "1 + 2 * 3" => "(+ 1 (* 2 3))",
             "5 + 4 * 3 / 4 + 5" => "(+ (+ 5 (/ (* 4 3) 4)) 5)",
+            "a + b % 2" => "(+ (Identifier a) (% (Identifier b) 2))",
+            "-2 ** 3 ** 2" => "(- (** 2 (** 3 2)))",
+            "7 // 2 * 3" => "(* (// 7 2) 3)",
+            "1 | 2 ^ 3 & 4 << 1 + 1" => "(| 1 (^ 2 (& 3 (<< 4 (+ 1 1)))))",
             "3 * (4 + 4)" => "(* 3 (+ 4 4))",
-            "(5 + 5) * 4" => "(* (+ 5 5) 4)"
+            "(5 + 5) * 4" => "(* (+ 5 5) 4)",
+            "\"a {x + 1} b\"" => "(+ (+ a  (format (+ (Identifier x) 1)))  b)",
+            "\"{x:.2}\"" => "(format (Identifier x))",
+            "a[1:2]" => "([:] (Identifier a) 1 2)",
+            "a[1:]" => "([:] (Identifier a) 1)",
+            "a[:b + 1]" => "([:] (Identifier a) 0 (+ (Identifier b) 1))"
         );
     }
 }
@@ -1,5 +1,7 @@
 use crate::{parse_expr, statement::Declaration, Lexer, Token, TokenType};
 
+use super::type_parse::parse_annotation;
+
 pub fn parse_declaration(lexer: &mut Lexer) -> Declaration {
     if let Token {
         ty: TokenType::Identifier,
@@ -7,12 +9,15 @@ pub fn parse_declaration(lexer: &mut Lexer) -> Declaration {
         ..
     } = lexer.next()
     {
+        let ty = parse_annotation(lexer);
         assert_eq!(lexer.next().ty, TokenType::Assign);
         Declaration {
             lhs: name,
             rhs: parse_expr(lexer),
             alias: true,
             plus_or_minus: None,
+            ty,
+            slot: None,
         }
     } else {
         panic!("error on parsing assignment");
@@ -1,9 +1,11 @@
+use crate::checker::Type;
 use crate::eval::atom::FunctionData;
 use crate::Token;
 use crate::S;
 use crate::{parse_expr, Lexer, TokenType};
 
 use super::parse_block;
+use super::type_parse::{parse_annotation, parse_type};
 
 pub fn parse_fn_dec(lexer: &mut Lexer) -> (String, FunctionData) {
     assert_eq!(lexer.next().ty, TokenType::Function);
@@ -19,7 +21,13 @@ pub fn parse_fn_dec(lexer: &mut Lexer) -> (String, FunctionData) {
         panic!("Invalid fn declaration")
     };
 
-    let arg_names = parse_fn_dec_args(lexer);
+    let (arg_names, arg_types) = parse_fn_dec_args(lexer).into_iter().unzip();
+    let return_type = if lexer.peek().ty == TokenType::Arrow {
+        lexer.next();
+        Some(parse_type(lexer))
+    } else {
+        None
+    };
 
     assert_eq!(lexer.next().ty, TokenType::LBrace);
     let fn_block = parse_block(lexer);
@@ -29,21 +37,25 @@ pub fn parse_fn_dec(lexer: &mut Lexer) -> (String, FunctionData) {
         fn_name,
         FunctionData {
             arg_names,
+            arg_types,
+            return_type,
             fn_block,
+            code: Default::default(),
         },
     )
 }
 
-pub fn parse_fn_dec_args(lexer: &mut Lexer) -> Vec<String> {
+pub fn parse_fn_dec_args(lexer: &mut Lexer) -> Vec<(String, Option<Type>)> {
     assert_eq!(lexer.next().ty, TokenType::LParen);
-    let args: Vec<String> = std::iter::from_fn(|| match lexer.peek().ty {
+    let args: Vec<(String, Option<Type>)> = std::iter::from_fn(|| match lexer.peek().ty {
         TokenType::RParen => None,
         TokenType::Identifier => {
             let arg_name = lexer.next().lexeme;
+            let ty = parse_annotation(lexer);
             if lexer.peek().ty == TokenType::Comma {
                 lexer.next();
             }
-            Some(arg_name)
+            Some((arg_name, ty))
         }
         _ => panic!("Invalid fn args in declaration"),
     })
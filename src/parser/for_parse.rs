@@ -6,7 +6,7 @@ use crate::{parse_block, parse_expr, statement::While, Lexer, TokenType};
 use super::S;
 
 pub fn parse_for(lexer: &mut Lexer) -> Stmt {
-    lexer.next();
+    let line = lexer.next().line;
 
     assert_eq!(lexer.next().ty, TokenType::LParen);
     let init_statement = if lexer.peek().ty != TokenType::Semicolon {
@@ -23,6 +23,7 @@ pub fn parse_for(lexer: &mut Lexer) -> Stmt {
     };
     assert_eq!(lexer.next().ty, TokenType::Semicolon);
 
+    let incr_line = lexer.peek().line;
     let incr = if lexer.peek().ty != TokenType::RParen {
         Some(parse_stmt(lexer))
     } else {
@@ -36,6 +37,7 @@ pub fn parse_for(lexer: &mut Lexer) -> Stmt {
 
     if let Some(Some(s)) = incr {
         loop_block.statements.push(s);
+        loop_block.lines.push(incr_line);
     }
 
     let while_stmt = While {
@@ -44,8 +46,10 @@ pub fn parse_for(lexer: &mut Lexer) -> Stmt {
     };
 
     if let Some(Some(s)) = init_statement {
-        Stmt::Block(Block::new(vec![s, Stmt::WhileStmt(while_stmt)]))
+        let stmts = vec![s, Stmt::WhileStmt(while_stmt)];
+        Stmt::Block(Block::with_lines(stmts, vec![line, line]))
     } else {
-        Stmt::Block(Block::new(vec![Stmt::WhileStmt(while_stmt)]))
+        let stmts = vec![Stmt::WhileStmt(while_stmt)];
+        Stmt::Block(Block::with_lines(stmts, vec![line]))
     }
 }
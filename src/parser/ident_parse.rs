@@ -26,6 +26,8 @@ pub fn parse_ident(lexer: &mut Lexer) -> Stmt {
             rhs: parse_expr(lexer),
             alias: false,
             plus_or_minus,
+            ty: None,
+            slot: None,
         })
     } else {
         lexer.prepend(nx);
@@ -0,0 +1,30 @@
+use crate::{eval::atom::Atom, statement::Import, Lexer, TokenType};
+
+// either `import "path"` or `import name from "path"`
+pub fn parse_import(lexer: &mut Lexer) -> Import {
+    lexer.next();
+
+    let name = if lexer.peek().ty == TokenType::Identifier {
+        let name = lexer.next().lexeme;
+        let from = lexer.next();
+        if from.ty != TokenType::Identifier || from.lexeme != "from" {
+            panic!("Expected from after import {} on line {}", name, from.line);
+        }
+        Some(name)
+    } else {
+        None
+    };
+
+    let path = lexer.next();
+    match path.ty {
+        TokenType::Literal(Atom::Str(path)) => Import {
+            name,
+            path,
+            slot: None,
+        },
+        _ => panic!(
+            "Expected a path like import \"file.slang\" on line {}",
+            path.line
+        ),
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{parse_expr, Lexer, Op, StrPart, S};
+
+use crate::eval::atom::Atom;
+
+// "a {x} b" becomes (+ (+ a (format x)) b)
+pub fn parse_interpolation(parts: Vec<StrPart>) -> S {
+    parts
+        .into_iter()
+        .map(|part| match part {
+            StrPart::Lit(s) => S::Atom(Atom::Str(s)),
+            StrPart::Expr(tokens, spec) => {
+                let mut lexer = Lexer::new(tokens);
+                let expr = parse_expr(&mut lexer);
+                assert!(
+                    lexer.is_empty(),
+                    "Invalid expression in interpolated string"
+                );
+                S::Cons(Op::Format(spec), vec![expr])
+            }
+        })
+        .reduce(|lhs, rhs| S::Cons(Op::Plus, vec![lhs, rhs]))
+        .unwrap()
+}
@@ -0,0 +1,20 @@
+use crate::checker::Type;
+use crate::{Lexer, TokenType};
+
+/// Parses the `: int` after a variable or argument name, if it's there
+pub fn parse_annotation(lexer: &mut Lexer) -> Option<Type> {
+    if lexer.peek().ty == TokenType::Colon {
+        lexer.next();
+        Some(parse_type(lexer))
+    } else {
+        None
+    }
+}
+
+pub fn parse_type(lexer: &mut Lexer) -> Type {
+    let t = lexer.next();
+    match (&t.ty, Type::from_name(&t.lexeme)) {
+        (TokenType::Identifier, Some(ty)) => ty,
+        _ => panic!("Unknown type {} on line {}", t.lexeme, t.line),
+    }
+}
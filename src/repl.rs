@@ -0,0 +1,464 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+use slang_treewalk::checker;
+use slang_treewalk::eval::atom::FunctionData;
+use slang_treewalk::parser::{parse_expr, Lexer, S};
+use slang_treewalk::scanner::token::TokenType;
+use slang_treewalk::scanner::{scan_tokens, KEYWORDS};
+use slang_treewalk::{panic_message, Atom, Interpreter, SlangError, State};
+
+const PROMPT: &str = "slang> ";
+const CONTINUE_PROMPT: &str = "  ... ";
+
+#[derive(Default)]
+struct ReplHelper {
+    // keywords and every name in scope, sorted, for tab completion
+    names: Vec<String>,
+    // parameter lists of the script functions in scope
+    params: HashMap<String, String>,
+}
+
+impl ReplHelper {
+    // the helper can't borrow the interpreter while it runs code, so
+    // it keeps a copy of what's in scope that's refreshed every line
+    fn refresh(&mut self, state: &State) {
+        let vars = state.scopes.iter().flat_map(|scope| scope.vars.iter());
+
+        self.names = KEYWORDS
+            .iter()
+            .map(|k| k.to_string())
+            .chain(vars.clone().map(|(name, _)| name.clone()))
+            .collect();
+        self.names.sort();
+        self.names.dedup();
+
+        self.params = vars
+            .filter_map(|(name, val)| match val {
+                Atom::Function(f) => Some((name.clone(), param_list(f))),
+                _ => None,
+            })
+            .collect();
+    }
+
+    // the start of the identifier before the cursor and the names
+    // it could be completed to
+    fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return (pos, vec![]);
+        }
+
+        let matches = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .cloned()
+            .collect();
+        (start, matches)
+    }
+
+    // shows a function's parameters after its name or an opening
+    // bracket, like "(a, b)" after "add"
+    fn params_hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+
+        let (line, open) = match line.strip_suffix('(') {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        let name = &line[word_start(line, line.len())..];
+        let params = self.params.get(name)?;
+        if open {
+            Some(params[1..].to_string())
+        } else {
+            Some(params.clone())
+        }
+    }
+}
+
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+// pressing enter on unfinished input starts a new line instead of
+// running it
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completions(line, pos))
+    }
+}
+
+// parameter hints are only there to read, so unlike the default
+// string hints they aren't inserted by the right arrow key
+struct ParamsHint(String);
+
+impl Hint for ParamsHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = ParamsHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context) -> Option<ParamsHint> {
+        self.params_hint(line, pos).map(ParamsHint)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+impl Helper for ReplHelper {}
+
+/// Whether the input has unclosed brackets or strings, in which
+/// case the REPL keeps reading lines
+pub fn is_incomplete(code: &str) -> bool {
+    let tokens = scan_tokens(code);
+    if let Some(TokenType::Error(msg)) = tokens.last().map(|t| &t.ty) {
+        return msg.starts_with("Unterminated");
+    }
+
+    let depth = tokens.iter().fold(0, |depth, t| match t.ty {
+        TokenType::LParen | TokenType::LBrace | TokenType::LBracket => depth + 1,
+        TokenType::RParen | TokenType::RBrace | TokenType::RBracket => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+const HELP: &str = "\
+:vars          show the variables that are defined
+:type <expr>   show the type of an expression without running it
+:ast <expr>    show how an expression is parsed
+:tokens <code> show the tokens code is scanned into
+:load <file>   run a file
+:reset         forget everything that's been defined
+:time <code>   run code and show how long it took
+:help          show this message";
+
+// commands start with a colon, like :vars. start is the interpreter
+// as it was before anything was typed in
+fn meta_command(
+    interp: &mut Interpreter,
+    start: &Interpreter,
+    command: &str,
+) -> Result<String, Box<dyn Error>> {
+    let (name, arg) = match command.find(char::is_whitespace) {
+        Some(i) => (&command[..i], command[i..].trim()),
+        None => (command, ""),
+    };
+
+    match name {
+        "vars" => Ok(show_vars(interp, start)),
+        "type" => {
+            let expr = parse(arg)?;
+            match checker::expr_type(&expr, interp.state()) {
+                Ok(ty) => Ok(ty.to_string()),
+                Err(errors) => {
+                    let msgs: Vec<String> = errors.into_iter().map(|e| e.msg).collect();
+                    Err(msgs.join("\n").into())
+                }
+            }
+        }
+        "ast" => Ok(parse(arg)?.to_string()),
+        "tokens" => {
+            let tokens: Vec<String> = scan_tokens(arg).iter().map(|t| t.to_string()).collect();
+            Ok(tokens.join("\n"))
+        }
+        "load" => {
+            let res = interp.eval_file(arg)?;
+            Ok(res.map_or_else(String::new, |a| a.to_string()))
+        }
+        "reset" => {
+            *interp = start.clone();
+            Ok("Reset".to_string())
+        }
+        "time" => {
+            let start = Instant::now();
+            let res = interp.eval_str(arg)?;
+            let elapsed = format!("took {:?}", start.elapsed());
+            Ok(match res {
+                Some(a) => format!("{}\n{}", a, elapsed),
+                None => elapsed,
+            })
+        }
+        "help" => Ok(HELP.to_string()),
+        _ => Err(format!("Unknown command :{}, try :help", name).into()),
+    }
+}
+
+// the parser reports errors by panicking
+fn parse(code: &str) -> Result<S, Box<dyn Error>> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut lexer = Lexer::new(scan_tokens(code));
+        parse_expr(&mut lexer)
+    }))
+    .map_err(|_| format!("Couldn't parse {}", code).into())
+}
+
+// the arguments and return type of a function, like "(a: int, b) -> int"
+fn param_list(f: &FunctionData) -> String {
+    let args: Vec<String> = f
+        .arg_names
+        .iter()
+        .zip(&f.arg_types)
+        .map(|(arg, ty)| match ty {
+            Some(ty) => format!("{}: {}", arg, ty),
+            None => arg.clone(),
+        })
+        .collect();
+
+    match f.return_type {
+        Some(ty) => format!("({}) -> {}", args.join(", "), ty),
+        None => format!("({})", args.join(", ")),
+    }
+}
+
+fn fn_signature(name: &str, f: &FunctionData) -> String {
+    format!("fn {}{}", name, param_list(f))
+}
+
+// functions can't be compared, so ones with the same parameters as
+// they started with are taken to be the same function
+fn unchanged(val: &Atom, old: Option<&Atom>) -> bool {
+    match (val, old) {
+        (Atom::Function(f), Some(Atom::Function(g))) => param_list(f) == param_list(g),
+        (v, old) => old == Some(v),
+    }
+}
+
+// builtins and the prelude are left out since there are so many
+// of them
+fn show_vars(interp: &Interpreter, start: &Interpreter) -> String {
+    let lines: Vec<String> = interp
+        .state()
+        .scopes
+        .iter()
+        .flat_map(|scope| scope.vars.iter())
+        .filter_map(|(name, val)| match val {
+            Atom::NativeFunction(_) => None,
+            v if unchanged(v, start.state().get_variable(name)) => None,
+            Atom::Function(f) => Some(fn_signature(name, f)),
+            Atom::Str(s) => Some(format!("{}: str = {:?}", name, s)),
+            v => Some(format!("{}: {} = {}", name, v.type_name(), v)),
+        })
+        .collect();
+
+    if lines.is_empty() {
+        "No variables".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".slang_history"))
+}
+
+pub fn run(interp: &mut Interpreter) -> Result<(), Box<dyn Error>> {
+    let start = interp.clone();
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = Editor::<ReplHelper>::with_config(config);
+    editor.set_helper(Some(ReplHelper::default()));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // there's no history the first time
+        let _ = editor.load_history(path);
+    }
+
+    let mut input = String::new();
+    let mut exit = None;
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.refresh(interp.state());
+        }
+
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE_PROMPT
+        };
+        match editor.readline(prompt) {
+            Ok(line) => input.push_str(&line),
+            // ctrl-c clears the input, ctrl-d quits
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        // the validator already handles this in a terminal, but not
+        // when input is piped in
+        if is_incomplete(&input) {
+            input.push('\n');
+            continue;
+        }
+        let input = std::mem::take(&mut input);
+
+        if input.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input.as_str());
+
+        if input.trim() == "exit" {
+            break;
+        }
+
+        // nothing typed in should end the session, even if it runs
+        // into a bug
+        let res = panic::catch_unwind(AssertUnwindSafe(|| match input.trim().strip_prefix(':') {
+            Some(command) => meta_command(interp, &start, command).map(Some),
+            None => interp
+                .eval_str(&input)
+                .map(|res| res.map(|a| a.to_string())),
+        }))
+        .unwrap_or_else(|payload| Err(panic_message(payload).into()));
+        match res {
+            Ok(Some(out)) => println!("{}", out),
+            Ok(None) => {}
+            Err(e) if matches!(e.downcast_ref(), Some(SlangError::Exit(_))) => {
+                exit = Some(e);
+                break;
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+
+    // exit() quits the REPL with its code
+    match exit {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("1 + 1"));
+        assert!(!is_incomplete("fn f(x) {\n x\n}"));
+        assert!(is_incomplete("fn f(x) {"));
+        assert!(is_incomplete("print(max(1,"));
+        assert!(is_incomplete("let a = [1,\n 2"));
+        assert!(is_incomplete("let s = \"abc"));
+        assert!(is_incomplete("let s = \"\"\"abc\n"));
+        assert!(!is_incomplete("let s = \"{x}\""));
+        // too many closing brackets is an error, not more input
+        assert!(!is_incomplete("1)"));
+        // and so is a typo
+        assert!(!is_incomplete("1 @ 2"));
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut interp = Interpreter::new();
+        let start = interp.clone();
+        interp
+            .eval_str("let x = 5\nlet s = \"a\"\nfn add(a: int, b) -> int {\n a + b\n}")
+            .unwrap();
+
+        let mut run = |command: &str| meta_command(&mut interp, &start, command).unwrap();
+        assert_eq!(run("ast 1 + 2 * x"), "(+ 1 (* 2 (Identifier x)))");
+        assert_eq!(run("type x / 2"), "float");
+        assert_eq!(run("type add(x, 1)"), "int");
+        assert_eq!(run("tokens x+1").lines().count(), 3);
+        assert_eq!(run("tokens x @ 1").lines().count(), 3);
+        assert!(run("time add(1, 2)").starts_with("3\ntook "));
+        assert_eq!(
+            run("vars"),
+            "fn add(a: int, b) -> int\ns: str = \"a\"\nx: int = 5"
+        );
+        assert_eq!(run("reset"), "Reset");
+
+        assert_eq!(
+            meta_command(&mut interp, &start, "vars").unwrap(),
+            "No variables"
+        );
+        assert!(interp.get_global("range").is_some());
+        assert!(meta_command(&mut interp, &start, "type x[").is_err());
+        assert!(meta_command(&mut interp, &start, "ast x @ 1").is_err());
+        assert!(meta_command(&mut interp, &start, "nope").is_err());
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut interp = Interpreter::new();
+        interp
+            .eval_str("let counter = 1\nfn add(a: int, b) -> int {\n a + b\n}")
+            .unwrap();
+        let mut helper = ReplHelper::default();
+        helper.refresh(interp.state());
+
+        let complete = |line: &str| helper.completions(line, line.len());
+        assert_eq!(complete("let x = cou"), (8, vec!["counter".to_string()]));
+        assert_eq!(complete("wh"), (0, vec!["while".to_string()]));
+        let (start, names) = complete("print(ad");
+        assert_eq!(start, 6);
+        assert!(names.contains(&"add".to_string()));
+        assert!(complete("le").1.contains(&"len".to_string()));
+        assert!(complete("1 + ").1.is_empty());
+
+        let hint = |line: &str| helper.params_hint(line, line.len());
+        assert_eq!(hint("add"), Some("(a: int, b) -> int".to_string()));
+        assert_eq!(hint("1 + add("), Some("a: int, b) -> int".to_string()));
+        assert_eq!(hint("counter"), None);
+        assert_eq!(helper.params_hint("add(1, 2)", 3), None);
+    }
+}
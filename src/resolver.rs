@@ -0,0 +1,224 @@
+//! Gives the variables declared inside blocks and functions a slot in
+//! their scope, so they're found by indexing into it instead of by
+//! name. This runs after parsing.
+//!
+//! Globals are left alone since they outlive the code declaring them,
+//! and so are variables a function uses without declaring them, which
+//! come from whoever calls it and are looked up by name.
+
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionCall, FunctionData, Local};
+use crate::module;
+use crate::parser::{Op, S};
+use crate::statement::{If, Stmt, While};
+
+/// Where a variable is: `index` in the scope `depth` scopes out from
+/// the innermost one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+#[derive(Default)]
+struct Resolver {
+    // the names declared in each block of the function being
+    // resolved, innermost last, which is empty at the top level
+    scopes: Vec<Vec<String>>,
+}
+
+/// Resolves the variables in a script, which runs in the global scope.
+pub fn resolve(block: &mut Block) {
+    Resolver::default().statements(block);
+}
+
+impl Resolver {
+    fn lookup(&self, name: &str) -> Option<Slot> {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, names)| {
+                let index = names.iter().position(|n| n == name)?;
+                Some(Slot { depth, index })
+            })
+    }
+
+    // declaring a name twice in a block gives it the same slot
+    fn declare(&mut self, name: &str) -> Option<Slot> {
+        let names = self.scopes.last_mut()?;
+        let index = match names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        };
+        Some(Slot { depth: 0, index })
+    }
+
+    fn statements(&mut self, block: &mut Block) {
+        for stmt in &mut block.statements {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.scopes.push(Vec::new());
+        self.statements(block);
+        block.locals = Rc::new(self.scopes.pop().unwrap());
+    }
+
+    // a function's parameters and the variables its body declares
+    // share a scope, and it can't see the blocks around it
+    fn function(&mut self, f: &mut FunctionData) {
+        let enclosing = std::mem::take(&mut self.scopes);
+        self.scopes.push(Vec::new());
+        for name in &f.arg_names {
+            self.declare(name);
+        }
+        self.statements(&mut f.fn_block);
+        f.fn_block.locals = Rc::new(self.scopes.pop().unwrap());
+        self.scopes = enclosing;
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::ExprStmt(expr) | Stmt::PrintStmt(expr) => self.expr(expr),
+            Stmt::Dec(dec) => {
+                // the value can use a variable the declaration shadows
+                self.expr(&mut dec.rhs);
+                dec.slot = if dec.alias {
+                    self.declare(&dec.lhs)
+                } else {
+                    self.lookup(&dec.lhs)
+                };
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                self.expr(cond);
+                self.block(then_block);
+                self.block(else_block);
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                self.expr(cond);
+                self.block(loop_block);
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Break => {}
+            Stmt::Import(import) => {
+                let name = match &import.name {
+                    Some(name) => Some(name.clone()),
+                    None => module::default_name(Path::new(&import.path)),
+                };
+                // a bad name is reported when the import runs
+                import.slot = name.and_then(|name| self.declare(&name));
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut S) {
+        match expr {
+            S::Atom(Atom::Identifier(name)) => {
+                if let Some(slot) = self.lookup(name) {
+                    let name = std::mem::take(name);
+                    *expr = S::Atom(Atom::Local(Local { name, slot }));
+                }
+            }
+            S::Atom(Atom::FnCall(FunctionCall { args, .. })) => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+            S::Atom(Atom::Array(xs)) => xs.iter_mut().for_each(|x| self.expr(x)),
+            // nothing else has the function yet, so this doesn't clone it
+            S::Atom(Atom::Function(f)) => self.function(Rc::make_mut(f)),
+            S::Atom(_) => {}
+            // the name after the dot belongs to what's being accessed,
+            // but the arguments of a call are evaluated here
+            S::Cons(Op::Access, xs) => {
+                if let [a, b] = xs.as_mut_slice() {
+                    self.expr(a);
+                    if let S::Atom(Atom::FnCall(FunctionCall { args, .. })) = b {
+                        args.iter_mut().for_each(|arg| self.expr(arg));
+                    }
+                }
+            }
+            S::Cons(_, xs) => xs.iter_mut().for_each(|x| self.expr(x)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use crate::{Atom, Backend, Interpreter};
+
+    fn resolved(code: &str) -> String {
+        crate::parse(code).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_resolve() {
+        // globals are looked up by name
+        assert_eq!(resolved("let x = 1\nx"), "(let x 1)\n(Identifier x)");
+        assert_eq!(
+            resolved("fn f(a, b) {\n    let c = a\n    while (c < b) {\n        c += 1\n    }\n    c + x\n}"),
+            "\
+(fn f (a b)
+  (let c (Local a 0 0))
+  (while (< (Local c 0 2) (Local b 0 1))
+    (+= c 1))
+  (+ (Local c 0 2) (Identifier x)))"
+        );
+        // a local is only resolved after it's declared
+        assert_eq!(
+            resolved("{\n    x\n    let x = x + 1\n    {\n        x\n    }\n}"),
+            "\
+(block
+  (Identifier x)
+  (let x (+ (Identifier x) 1))
+  (block
+    (Local x 1 0)))"
+        );
+    }
+
+    #[test]
+    fn test_slots_by_name() {
+        let code = "\
+fn get_y() {
+    y
+}
+fn f(a) {
+    let y = a * 2
+    let arr = [a]
+    push(arr, get_y())
+    {
+        y += 1
+        let y = 0
+    }
+    [y, arr]
+}
+f(3)";
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            let mut interp = Interpreter::new();
+            interp.set_backend(backend);
+            let res = interp.eval_str(code).unwrap();
+            // functions see their callers' locals, and natives can
+            // write back to them
+            let expected = interp.eval_str("[7, [3, 6]]").unwrap();
+            assert_eq!(res, expected, "with {:?}", backend);
+            assert_eq!(interp.state().scopes.len(), 1);
+        }
+        let mut interp = Interpreter::new();
+        assert_eq!(
+            interp
+                .eval_str("fn f(a, b) {\n    a + b\n}\nlet a = 5\nf(1, a)")
+                .unwrap(),
+            Some(Atom::Int(6))
+        );
+    }
+}
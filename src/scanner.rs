@@ -1,148 +1,410 @@
 pub mod token;
 use token::*;
 
-use crate::eval::atom::Atom;
+use crate::eval::atom::{Atom, FormatSpec};
 
-use itertools::Itertools;
+use num_bigint::BigInt;
 
-fn skip_comment(source: &[char]) -> Vec<char> {
-    source
-        .iter()
-        .skip_while(|&&c| c != '\n' && c != '\r')
-        .copied()
-        .collect_vec()
+fn escape_char(source: &str) -> Result<(char, usize), String> {
+    let mut chars = source.chars();
+    let c = match chars.next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some('\\') => '\\',
+        Some('\"') => '\"',
+        Some('\'') => '\'',
+        Some('u') if chars.as_str().starts_with('{') => {
+            let xs = &chars.as_str()[1..];
+            let hex = match xs.find('}') {
+                Some(end) => &xs[..end],
+                None => return Err("Unterminated unicode escape".to_string()),
+            };
+            // from_str_radix would take a sign too
+            if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid unicode escape \\u{{{}}}", hex));
+            }
+            return match u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(std::char::from_u32)
+            {
+                Some(c) => Ok((c, hex.len() + 3)),
+                None => Err(format!("Invalid unicode escape \\u{{{}}}", hex)),
+            };
+        }
+        Some(c) => return Err(format!("Invalid escape \\{}", c)),
+        None => return Err("Unterminated string starting".to_string()),
+    };
+    Ok((c, 1))
 }
 
-fn string_token(source: &[char]) -> Vec<Token> {
-    let cond = |&&c: &&char| c != '\"';
-    let s: String = source.iter().take_while(cond).collect();
-    let remaining = source
-        .iter()
-        .skip_while(cond)
-        .skip(1)
-        .copied()
-        .collect_vec();
-    [
-        vec![Token::new(TokenType::Literal(Atom::Str(s.clone())), s, 0)],
-        token_recurse(&remaining),
-    ]
-    .concat()
+// finds the } closing an interpolated expression, skipping over
+// nested braces and string literals
+//
+// everything it looks for is ASCII, so it can go byte by byte
+fn interpolation_len(source: &str) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (in_string, bytes[i]) {
+            (true, b'\\') => i += 1,
+            (_, b'\"') => in_string = !in_string,
+            (false, b'{') => depth += 1,
+            (false, b'}') if depth == 0 => return Some(i),
+            (false, b'}') => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
 }
 
-fn num_token(source: &[char]) -> Vec<Token> {
-    let cond = |&&c: &&char| c.is_numeric() || c == '.';
-    let s: String = source.iter().take_while(cond).collect();
+// finds the : before a format spec, which can't be inside brackets or a string
+fn format_spec_colon(source: &str) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut colon = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (in_string, bytes[i]) {
+            (true, b'\\') => i += 1,
+            (_, b'\"') => in_string = !in_string,
+            (false, b'(' | b'[' | b'{') => depth += 1,
+            (false, b')' | b']' | b'}') => depth -= 1,
+            (false, b':') if depth == 0 => colon = Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
 
-    let n = if let Ok(n) = s.parse::<isize>() {
-        Token::new(TokenType::Literal(Atom::Int(n)), s, 0)
-    } else if let Ok(n) = s.parse::<f64>() {
-        Token::new(TokenType::Literal(Atom::Float(n)), s, 0)
-    } else {
-        Token::new(TokenType::Unknown, s, 0)
+    colon
+}
+
+fn interpolation_part(source: &str, line: usize) -> Result<StrPart, String> {
+    let (expr, spec) = match format_spec_colon(source) {
+        Some(i) => {
+            let spec = &source[i + 1..];
+            match FormatSpec::parse(spec) {
+                Some(spec) => (&source[..i], spec),
+                None => return Err(format!("Invalid format spec {}", spec)),
+            }
+        }
+        _ => (source, FormatSpec::default()),
     };
 
-    let remaining = source.iter().skip_while(cond).copied().collect_vec();
+    let tokens = Scanner::new(expr, line).scan();
+    match tokens.last() {
+        Some(Token {
+            ty: TokenType::Error(msg),
+            ..
+        }) => Err(msg.clone()),
+        None => Err("Empty interpolation".to_string()),
+        _ => Ok(StrPart::Expr(tokens, spec)),
+    }
+}
 
-    [vec![n], token_recurse(&remaining)].concat()
+fn count_lines(s: &str) -> usize {
+    s.bytes().filter(|&b| b == b'\n').count()
 }
 
-fn ident_token(source: &[char]) -> Vec<Token> {
-    fn is_ident_char(c: &&char) -> bool {
-        match &c {
-            c if c.is_alphanumeric() => true,
-            '_' => true,
-            _ => false,
+// reads a string literal up to and including the terminator, returning the
+// parts of the string and the number of bytes consumed
+//
+// raw strings don't process escapes or interpolation
+fn string_contents(
+    source: &str,
+    terminator: &str,
+    raw: bool,
+    line: usize,
+) -> Result<(Vec<StrPart>, usize), String> {
+    let mut parts = Vec::new();
+    let mut s = String::new();
+    let mut i = 0;
+
+    while !source[i..].starts_with(terminator) {
+        let rest = &source[i..];
+        let mut chars = rest.chars();
+        match chars.next() {
+            None => return Err("Unterminated string starting".to_string()),
+            Some('\\') if !raw => {
+                let (c, len) = escape_char(chars.as_str())?;
+                s.push(c);
+                i += len + 1;
+            }
+            Some(c @ ('{' | '}')) if !raw && chars.as_str().starts_with(c) => {
+                s.push(c);
+                i += 2;
+            }
+            Some('{') if !raw => {
+                let len = match interpolation_len(chars.as_str()) {
+                    Some(len) => len,
+                    None => return Err("Unterminated interpolation in string starting".to_string()),
+                };
+                let expr_line = line + count_lines(&source[..i]);
+                let expr = &rest[1..1 + len];
+
+                parts.push(StrPart::Lit(std::mem::take(&mut s)));
+                parts.push(interpolation_part(expr, expr_line)?);
+                i += len + 2;
+            }
+            Some(c) => {
+                s.push(c);
+                i += c.len_utf8();
+            }
         }
     }
 
-    let lex: String = source.iter().take_while(is_ident_char).collect();
-    let remaining = source
-        .iter()
-        .skip_while(is_ident_char)
-        .copied()
-        .collect_vec();
-    macro_rules! add_lexemes {
-        ( $($lex:expr => $ty:expr),* ) => {
-            match lex.as_str() {
-                $( $lex => Token::from_ty($ty), )*
-                _ => Token::new(TokenType::Identifier, lex.clone(), 0),
-            };
+    parts.push(StrPart::Lit(s));
+    parts.retain(|p| p != &StrPart::Lit(String::new()));
+    Ok((parts, i + terminator.len()))
+}
+
+macro_rules! add_lexemes {
+    ( $($lex:expr => $ty:expr),* ) => {
+        /// Every reserved word, in the order they're matched
+        pub const KEYWORDS: &[&str] = &[$($lex),*];
+
+        fn keyword(lex: &str) -> Option<TokenType> {
+            match lex {
+                $( $lex => Some($ty), )*
+                _ => None,
+            }
         }
     }
+}
 
-    let token = add_lexemes!(
-        "let" => TokenType::Let,
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        "elif" => TokenType::Elif,
-        "true" => TokenType::True,
-        "false" => TokenType::False,
-        "for" => TokenType::For,
-        "while" => TokenType::While,
-        "fn" => TokenType::Function,
-        "struct" => TokenType::Struct,
-        "break" => TokenType::Break,
-        "print" => TokenType::Print
-    );
-
-    [vec![token], token_recurse(&remaining)].concat()
+add_lexemes!(
+    "let" => TokenType::Let,
+    "if" => TokenType::If,
+    "else" => TokenType::Else,
+    "elif" => TokenType::Elif,
+    "true" => TokenType::True,
+    "false" => TokenType::False,
+    "for" => TokenType::For,
+    "while" => TokenType::While,
+    "fn" => TokenType::Function,
+    "struct" => TokenType::Struct,
+    "break" => TokenType::Break,
+    "print" => TokenType::Print,
+    "import" => TokenType::Import
+);
+
+// operators and punctuation, returning the token type and its length
+fn symbol_token(source: &str) -> Option<(TokenType, usize)> {
+    let res = match source.as_bytes() {
+        [b'&', b'&', ..] => (TokenType::And, 2),
+        [b'|', b'|', ..] => (TokenType::Or, 2),
+        [b'+', b'=', ..] => (TokenType::PlusAssign, 2),
+        [b'-', b'>', ..] => (TokenType::Arrow, 2),
+        [b'/', b'/', ..] => (TokenType::SlashSlash, 2),
+        [b'*', b'*', ..] => (TokenType::StarStar, 2),
+        [b'<', b'<', ..] => (TokenType::LessLess, 2),
+        [b'>', b'>', ..] => (TokenType::GreaterGreater, 2),
+        [b'!', b'=', ..] => (TokenType::BangEqual, 2),
+        [b'<', b'=', ..] => (TokenType::LessEqual, 2),
+        [b'>', b'=', ..] => (TokenType::GreaterEqual, 2),
+        [b'=', b'=', ..] => (TokenType::Equal, 2),
+        [b'=', ..] => (TokenType::Assign, 1),
+        [b'<', ..] => (TokenType::Less, 1),
+        [b'>', ..] => (TokenType::Greater, 1),
+        [b'(', ..] => (TokenType::LParen, 1),
+        [b')', ..] => (TokenType::RParen, 1),
+        [b'{', ..] => (TokenType::LBrace, 1),
+        [b'}', ..] => (TokenType::RBrace, 1),
+        [b',', ..] => (TokenType::Comma, 1),
+        [b'*', ..] => (TokenType::Star, 1),
+        [b'/', ..] => (TokenType::Slash, 1),
+        [b'-', ..] => (TokenType::Minus, 1),
+        [b'+', ..] => (TokenType::Plus, 1),
+        [b'%', ..] => (TokenType::Percent, 1),
+        [b'&', ..] => (TokenType::Ampersand, 1),
+        [b'|', ..] => (TokenType::Pipe, 1),
+        [b'^', ..] => (TokenType::Caret, 1),
+        [b'.', ..] => (TokenType::Dot, 1),
+        [b';', ..] => (TokenType::Semicolon, 1),
+        [b':', ..] => (TokenType::Colon, 1),
+        [b'[', ..] => (TokenType::LBracket, 1),
+        [b']', ..] => (TokenType::RBracket, 1),
+        [b'!', ..] => (TokenType::Bang, 1),
+        _ => return None,
+    };
+    Some(res)
+}
+
+// the length of the start of source where every char passes cond
+fn prefix_len(source: &str, cond: impl Fn(char) -> bool) -> usize {
+    source.find(|c| !cond(c)).unwrap_or(source.len())
+}
+
+// walks the source once, keeping the byte offset of the next char
+// rather than copying what's left of the source
+struct Scanner<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    tokens: Vec<Token>,
 }
 
-// this could probably be many times faster but
-// the evaluation will always take much longer
-// than the scanner
-fn token_recurse(source: &[char]) -> Vec<Token> {
-    fn recur(ty: TokenType, s: &[char]) -> Vec<Token> {
-        [vec![Token::from_ty(ty)], token_recurse(s)].concat()
-    }
-
-    match &source {
-        [] | ['\n' | '\r'] => vec![],
-        ['#', ..] => token_recurse(skip_comment(source).as_slice()),
-        ['\n' | '\r', xs @ ..] => recur(TokenType::NewLine, xs),
-        ['&', '&', xs @ ..] => recur(TokenType::And, xs),
-        ['|', '|', xs @ ..] => recur(TokenType::Or, xs),
-        ['+', '=', xs @ ..] => recur(TokenType::PlusAssign, xs),
-        ['!', '=', xs @ ..] => recur(TokenType::BangEqual, xs),
-        ['<', '=', xs @ ..] => recur(TokenType::LessEqual, xs),
-        ['>', '=', xs @ ..] => recur(TokenType::GreaterEqual, xs),
-        ['=', '=', xs @ ..] => recur(TokenType::Equal, xs),
-        ['=', xs @ ..] => recur(TokenType::Assign, xs),
-        ['<', xs @ ..] => recur(TokenType::Less, xs),
-        ['>', xs @ ..] => recur(TokenType::Greater, xs),
-        ['(', xs @ ..] => recur(TokenType::LParen, xs),
-        [')', xs @ ..] => recur(TokenType::RParen, xs),
-        ['{', xs @ ..] => recur(TokenType::LBrace, xs),
-        ['}', xs @ ..] => recur(TokenType::RBrace, xs),
-        [',', xs @ ..] => recur(TokenType::Comma, xs),
-        ['*', xs @ ..] => recur(TokenType::Star, xs),
-        ['/', xs @ ..] => recur(TokenType::Slash, xs),
-        ['-', xs @ ..] => recur(TokenType::Minus, xs),
-        ['+', xs @ ..] => recur(TokenType::Plus, xs),
-        ['%', xs @ ..] => recur(TokenType::Percent, xs),
-        ['.', xs @ ..] => recur(TokenType::Dot, xs),
-        [';', xs @ ..] => recur(TokenType::Semicolon, xs),
-        ['[', xs @ ..] => recur(TokenType::LBracket, xs),
-        [']', xs @ ..] => recur(TokenType::RBracket, xs),
-        ['!', xs @ ..] => recur(TokenType::Bang, xs),
-        ['\"', xs @ ..] => string_token(xs),
-        ls @ [c, ..] if c.is_numeric() => num_token(ls),
-        ls @ [c, ..] if c.is_alphabetic() => ident_token(ls),
-        [c, xs @ ..] if c.is_whitespace() => token_recurse(xs),
-        c => panic!("Invalid input {:?}", c),
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str, line: usize) -> Self {
+        Scanner {
+            source,
+            pos: 0,
+            line,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn push(&mut self, ty: TokenType, lexeme: String) {
+        self.tokens.push(Token::new(ty, lexeme, self.line));
+    }
+
+    fn scan(mut self) -> Vec<Token> {
+        loop {
+            self.pos += prefix_len(self.rest(), |c| c.is_whitespace() && c != '\n' && c != '\r');
+            let rest = self.rest();
+
+            if let Some((ty, len)) = symbol_token(rest) {
+                self.push(ty, String::new());
+                self.pos += len;
+                continue;
+            }
+
+            let mut chars = rest.chars();
+            let c = match chars.next() {
+                Some(c) => c,
+                None => break,
+            };
+            match c {
+                // a newline at the very end doesn't get a token
+                '\n' | '\r' if chars.as_str().is_empty() => break,
+                '#' => self.pos += prefix_len(rest, |c| c != '\n' && c != '\r'),
+                '\n' | '\r' => {
+                    self.push(TokenType::NewLine, String::new());
+                    self.pos += if rest.starts_with("\r\n") { 2 } else { 1 };
+                    self.line += 1;
+                }
+                '\"' => {
+                    let terminator = if rest.starts_with("\"\"\"") {
+                        "\"\"\""
+                    } else {
+                        "\""
+                    };
+                    self.pos += terminator.len();
+                    if !self.string(terminator, false) {
+                        break;
+                    }
+                }
+                'r' => match self.raw_string_start() {
+                    Some(hashes) => {
+                        // the terminator is the opening quote and hashes
+                        // the other way around
+                        let terminator = format!("\"{}", "#".repeat(hashes));
+                        self.pos += hashes + 2;
+                        if !self.string(&terminator, true) {
+                            break;
+                        }
+                    }
+                    None => self.ident(),
+                },
+                c if c.is_numeric() => self.number(),
+                c if c.is_alphabetic() => self.ident(),
+                // the parser reports these, like numbers it can't read
+                c => {
+                    self.push(TokenType::Unknown, c.to_string());
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+
+        self.tokens
+    }
+
+    // raw strings look like r"..." or r#"..."# with any number of #s,
+    // this gives the number of #s if the source is at the start of one
+    fn raw_string_start(&self) -> Option<usize> {
+        let rest = &self.rest()[1..];
+        let hashes = prefix_len(rest, |c| c == '#');
+        if rest[hashes..].starts_with('\"') {
+            Some(hashes)
+        } else {
+            None
+        }
+    }
+
+    // scans a string after its opening quote, returning false if it
+    // doesn't end, since the rest of the source is unusable then
+    fn string(&mut self, terminator: &str, raw: bool) -> bool {
+        let rest = self.rest();
+        match string_contents(rest, terminator, raw, self.line) {
+            Ok((parts, len)) => {
+                let (ty, lexeme) = match parts.as_slice() {
+                    [] => (TokenType::Literal(Atom::Str(String::new())), String::new()),
+                    [StrPart::Lit(s)] => (TokenType::Literal(Atom::Str(s.clone())), s.clone()),
+                    _ => {
+                        let lexeme = rest[..len - terminator.len()].to_string();
+                        (TokenType::StrInterp(parts), lexeme)
+                    }
+                };
+                self.push(ty, lexeme);
+                self.line += count_lines(&rest[..len]);
+                self.pos += len;
+                true
+            }
+            Err(msg) => {
+                self.push(TokenType::Error(msg), String::new());
+                false
+            }
+        }
+    }
+
+    fn number(&mut self) {
+        let rest = self.rest();
+        let s = &rest[..prefix_len(rest, |c| c.is_numeric() || c == '.')];
+
+        let ty = if let Ok(n) = s.parse::<isize>() {
+            TokenType::Literal(Atom::Int(n))
+        } else if let Ok(n) = s.parse::<BigInt>() {
+            TokenType::Literal(Atom::BigInt(n))
+        } else if let Ok(n) = s.parse::<f64>() {
+            TokenType::Literal(Atom::Float(n))
+        } else {
+            TokenType::Unknown
+        };
+        self.push(ty, s.to_string());
+        self.pos += s.len();
+    }
+
+    fn ident(&mut self) {
+        let rest = self.rest();
+        let lex = &rest[..prefix_len(rest, |c| c.is_alphanumeric() || c == '_')];
+        match keyword(lex) {
+            Some(ty) => self.push(ty, String::new()),
+            None => self.push(TokenType::Identifier, lex.to_string()),
+        }
+        self.pos += lex.len();
     }
 }
 
 pub fn scan_tokens(source: &str) -> Vec<Token> {
-    let slice = source.chars().collect_vec();
-    let token_slice = token_recurse(&slice);
-    token_slice.iter().cloned().collect_vec()
+    Scanner::new(source, 1).scan()
 }
 
 #[cfg(test)]
 mod lexer_tests {
     use super::scan_tokens;
-    use crate::eval::atom::Atom;
+    use crate::eval::atom::{Atom, FormatSpec};
     use crate::scanner::token::*;
 
     macro_rules! test_lexer {
@@ -153,30 +415,162 @@ mod lexer_tests {
         }
     }
 
+    fn token(ty: TokenType) -> Token {
+        Token::new(ty, String::new(), 1)
+    }
+
     fn string(s: &str) -> Token {
         Token::new(
-            TokenType::Literal(Atom::Str(s.clone().to_string())),
+            TokenType::Literal(Atom::Str(s.to_string())),
             s.to_string(),
-            0,
+            1,
         )
     }
 
     fn identifier(n: &str) -> Token {
-        Token::new(TokenType::Identifier, n.to_string(), 0)
+        Token::new(TokenType::Identifier, n.to_string(), 1)
     }
 
     #[test]
     fn test_lexer() {
         test_lexer!(
-            "()" => [TokenType::LParen, TokenType::RParen].iter().map(|ty| Token::from_ty(ty.clone())),
-            "(  ) \"asdf\" " => [Token::from_ty(TokenType::LParen),
-            Token::from_ty(TokenType::RParen),
+            "()" => [TokenType::LParen, TokenType::RParen].iter().map(|ty| token(ty.clone())),
+            "(  ) \"asdf\" " => [token(TokenType::LParen),
+            token(TokenType::RParen),
             string("asdf")].iter().cloned(),
             ">= \"hello#notcomment\" identifier_here # yes comment" =>
-            [Token::from_ty(TokenType::GreaterEqual),
+            [token(TokenType::GreaterEqual),
             string("hello#notcomment"),
             identifier("identifier_here")
             ].iter().cloned()
         );
     }
+
+    #[test]
+    fn test_strings() {
+        test_lexer!(
+            r#""a\tb\n\"c\" \\ \u{1F600}""# => [string("a\tb\n\"c\" \\ \u{1F600}")].iter().cloned(),
+            r##"r"C:\path" r#"say "hi""#"## => [string("C:\\path"), string("say \"hi\"")].iter().cloned(),
+            "r # comment" => [identifier("r")].iter().cloned()
+        );
+
+        let tokens = scan_tokens("\"\"\"first\nsecond \"quoted\" \"\"\" \"\"\nx");
+        assert_eq!(
+            tokens[0].ty,
+            TokenType::Literal(Atom::Str("first\nsecond \"quoted\" ".to_string()))
+        );
+        assert_eq!(tokens[1].ty, TokenType::Literal(Atom::Str("".to_string())));
+        assert_eq!(tokens[3].line, 3);
+
+        let tokens = scan_tokens("let a = 1\nlet b = \"abc\n\nlet c = 2");
+        assert_eq!(
+            tokens.last(),
+            Some(&Token::new(
+                TokenType::Error("Unterminated string starting".to_string()),
+                String::new(),
+                2
+            ))
+        );
+        // only unterminated strings point at where they start
+        let error = |source: &str| scan_tokens(source).last().unwrap().ty.clone();
+        assert_eq!(
+            error("\"\\q\""),
+            TokenType::Error("Invalid escape \\q".to_string())
+        );
+        assert_eq!(
+            error("\"\\u{+41}\""),
+            TokenType::Error("Invalid unicode escape \\u{+41}".to_string())
+        );
+        assert_eq!(
+            error("\"a {x\""),
+            TokenType::Error("Unterminated interpolation in string starting".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let spec = FormatSpec {
+            width: Some(8),
+            precision: Some(2),
+            zero_pad: false,
+        };
+        assert_eq!(
+            scan_tokens("\"at {x:8.2}!\"")[0].ty,
+            TokenType::StrInterp(vec![
+                StrPart::Lit("at ".to_string()),
+                StrPart::Expr(vec![identifier("x")], spec),
+                StrPart::Lit("!".to_string()),
+            ])
+        );
+
+        assert_eq!(scan_tokens("\"{{}}\""), vec![string("{}")]);
+        assert_eq!(scan_tokens(r#"r"{x}""#), vec![string("{x}")]);
+        assert!(matches!(
+            scan_tokens("\"{x\"").last().unwrap().ty,
+            TokenType::Error(_)
+        ));
+        assert!(matches!(
+            scan_tokens("\"{x:q}\"").last().unwrap().ty,
+            TokenType::Error(_)
+        ));
+        assert_eq!(
+            scan_tokens("\"{x:+5}\"").last().unwrap().ty,
+            TokenType::Error("Invalid format spec +5".to_string())
+        );
+        assert_eq!(
+            scan_tokens("\"{x:5.+2}\"").last().unwrap().ty,
+            TokenType::Error("Invalid format spec 5.+2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        test_lexer!(
+            "1 @ é€" => [
+                Token::new(TokenType::Literal(Atom::Int(1)), "1".to_string(), 1),
+                Token::new(TokenType::Unknown, "@".to_string(), 1),
+                identifier("é"),
+                Token::new(TokenType::Unknown, "€".to_string(), 1)
+            ].iter().cloned()
+        );
+        let err = crate::parse("let x = 1\n1 @ 2").unwrap_err();
+        assert_eq!(
+            err,
+            crate::SlangError::Syntax("Invalid input \"@\" on line 2".to_string())
+        );
+    }
+
+    // big enough that scanning a token per stack frame, or copying
+    // what's left of the source for every token, would never finish
+    #[test]
+    fn test_large_source() {
+        let n = 100_000;
+        let source = "let x_1 = 12 + \"s {y} é\" # comment\n".repeat(n);
+        assert!(source.len() > 3_000_000);
+        let tokens = scan_tokens(&source);
+        // the last newline doesn't get a token
+        assert_eq!(tokens.len(), 7 * n - 1);
+        assert_eq!(tokens.last().unwrap().line, n);
+
+        let source = "1 + ".repeat(1_000_000) + "1";
+        let tokens = scan_tokens(&source);
+        assert_eq!(tokens.len(), 2_000_001);
+        assert_eq!(tokens.last().unwrap().line, 1);
+
+        let text = "é\n".repeat(1_000_000);
+        let tokens = scan_tokens(&format!("\"{}\" x", text));
+        assert_eq!(tokens[0], string(&text));
+        assert_eq!(
+            tokens[1],
+            Token::new(TokenType::Identifier, "x".to_string(), 1_000_001)
+        );
+    }
+
+    #[test]
+    fn test_large_program() {
+        let source = format!("let x = 0\n{}x", "x += 1\n".repeat(200_000));
+        assert!(source.len() > 1_000_000);
+        let mut interp = crate::Interpreter::new();
+        assert_eq!(interp.eval_str(&source).unwrap(), Some(Atom::Int(200_000)));
+    }
 }
@@ -1,4 +1,4 @@
-use crate::eval::atom::Atom;
+use crate::eval::atom::{Atom, FormatSpec};
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)]
@@ -17,8 +17,15 @@ pub enum TokenType {
     Minus,
     MinusAssign,
     Slash,
+    SlashSlash,
     Star,
+    StarStar,
     Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    LessLess,
+    GreaterGreater,
     Bang,
     BangEqual,
     Equal,
@@ -29,6 +36,7 @@ pub enum TokenType {
     LessEqual,
     Identifier,
     Literal(Atom),
+    StrInterp(Vec<StrPart>),
     And,
     Or,
     Struct,
@@ -44,10 +52,21 @@ pub enum TokenType {
     EOF,
     Hash,
     Semicolon,
+    Colon,
+    Arrow,
     Break,
+    Import,
     LBracket,
     RBracket,
     Unknown,
+    Error(String),
+}
+
+/// A piece of an interpolated string like "ball at {x:.2}"
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    Lit(String),
+    Expr(Vec<Token>, FormatSpec),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -1,84 +1,320 @@
+use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 use crate::{
     block::Block,
-    eval::{atom::Atom, eval_expr},
+    checker::Type,
+    eval::{
+        atom::{Atom, NativeFunction},
+        eval_expr, install_builtins,
+    },
+    module::{self, Modules},
     parser::*,
+    resolver::Slot,
+    Backend,
 };
 
 #[derive(Debug, Clone)]
 pub struct State {
     pub scopes: Vec<Scope>,
+    /// The file being run, which imports are relative to
+    pub path: Option<PathBuf>,
+    /// Shared with the states of every module that gets imported
+    pub modules: Rc<RefCell<Modules>>,
+    /// How many statements the tree-walker has run, if they're being
+    /// counted, which only the bench command does. The VM doesn't
+    /// count them.
+    pub steps: Option<u64>,
+    /// Whether print statements are skipped, though what they'd
+    /// print is still evaluated
+    pub quiet: bool,
+    /// How code is run, which includes the functions that natives and
+    /// modules call
+    pub backend: Backend,
 }
 
 impl Default for State {
     fn default() -> Self {
-        State {
+        let mut state = State {
             scopes: vec![Scope::default()],
-        }
+            path: None,
+            modules: Rc::default(),
+            steps: None,
+            quiet: false,
+            backend: Backend::default(),
+        };
+        install_builtins(&mut state);
+        state
     }
 }
 
 impl State {
     pub fn get_variable(&self, var: &str) -> Option<&Atom> {
-        self.scopes
-            .iter()
+        self.scopes.iter().rev().find_map(|scope| scope.get(var))
+    }
+
+    /// The value in a slot the resolver found, unless the variable
+    /// hasn't been declared yet.
+    pub fn get_local(&self, slot: Slot) -> Option<&Atom> {
+        let depth = self.scopes.len() - 1 - slot.depth;
+        self.scopes[depth].slots.get(slot.index)
+    }
+
+    pub fn set_local(&mut self, slot: Slot, val: Atom) {
+        let depth = self.scopes.len() - 1 - slot.depth;
+        if let Some(var) = self.scopes[depth].slots.get_mut(slot.index) {
+            *var = val;
+        }
+    }
+
+    // a variable's value, by slot if the resolver found one
+    fn lookup(&self, name: &str, slot: Option<Slot>) -> Option<&Atom> {
+        match slot {
+            Some(slot) => self.get_local(slot),
+            None => self.get_variable(name),
+        }
+    }
+
+    /// Declares a function implemented in Rust in the global scope.
+    /// Scripts can shadow it like any other global.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        func: impl Fn(&mut State, &mut [Atom]) -> Atom + 'static,
+    ) {
+        self.register_mutating_fn(name, arity, &[], func);
+    }
+
+    /// Like `register_fn`, but the arguments at the indexes in
+    /// `mutates` are written back to the variables they came from, the
+    /// way `push` modifies its array.
+    pub fn register_mutating_fn(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        mutates: &'static [usize],
+        func: impl Fn(&mut State, &mut [Atom]) -> Atom + 'static,
+    ) {
+        let native = Atom::NativeFunction(NativeFunction {
+            name: name.to_string(),
+            arity,
+            mutates,
+            func: Rc::new(func),
+        });
+        // modules can use it too
+        self.modules
+            .borrow_mut()
+            .base
+            .vars
+            .insert(name.to_string(), native.clone());
+        self.scopes[0].vars.insert(name.to_string(), native);
+    }
+
+    pub fn modify_variable(&mut self, var: &str, val: Atom) {
+        if let Some(old) = self
+            .scopes
+            .iter_mut()
             .rev()
-            .find_map(|scope| scope.vars.get(var))
+            .find_map(|scope| scope.get_mut(var))
+        {
+            *old = val;
+        }
     }
 
-    fn modify_variable(&mut self, var: &str, val: Atom) {
-        // dbg!(self.scopes.clone());
-        for scope in &mut self.scopes.iter_mut().rev() {
-            if scope.vars.contains_key(var) {
-                scope.vars.insert(var.to_string(), val);
-                break;
+    pub fn declare(&mut self, dec: &Declaration) {
+        if dec.alias {
+            let val = eval_expr(&dec.rhs, self);
+            match dec.slot {
+                Some(slot) => self.declare_local(&dec.lhs, slot.index, val, dec.ty),
+                None => self.declare_value(&dec.lhs, val, dec.ty),
             }
+        } else {
+            let old_val = match self.lookup(&dec.lhs, dec.slot) {
+                Some(v) => v.clone(),
+                None => panic!("Uninitialized variable {}", dec.lhs),
+            };
+            let rhs_val = eval_expr(&dec.rhs, self);
+            self.assign(&dec.lhs, dec.slot, old_val, rhs_val, dec.plus_or_minus);
         }
     }
 
-    pub fn declare(&mut self, dec: Declaration) {
-        let (val, disc) = {
-            let var = self.get_variable(&dec.lhs);
-            (var.cloned(), var.map(std::mem::discriminant))
-        };
+    /// Declares a variable in the innermost scope, checking it against
+    /// its annotation if it has one.
+    pub fn declare_value(&mut self, name: &str, val: Atom, ty: Option<Type>) {
+        check_annotation(name, &val, ty);
+        let vars = &mut self.scopes.last_mut().unwrap().vars;
+        match vars.get_mut(name) {
+            Some(old) => *old = val,
+            None => vars.insert(name.to_string(), val),
+        }
+    }
 
-        match (disc, dec.alias) {
-            (_, true) => {
-                let new_val = eval_expr(&dec.rhs, self);
-                self.scopes
-                    .last_mut()
-                    .unwrap()
-                    .vars
-                    .insert(dec.lhs, new_val);
-            }
-            (Some(d), false) => {
-                let rhs_val = eval_expr(&dec.rhs, self);
-                if d == std::mem::discriminant(&&rhs_val) {
-                    // dbg!(dec.lhs.clone(), val.clone());
-                    let new_val = match dec.plus_or_minus {
-                        Some(true) => val.unwrap() + rhs_val,
-                        Some(false) => val.unwrap() - rhs_val,
-                        None => rhs_val,
-                    };
-
-                    // dbg!(new_val.clone());
-                    self.modify_variable(&dec.lhs, new_val);
-                    // dbg!(self.get_variable(&dec.lhs));
-                } else {
-                    panic!("Cannot assign {:?} to {:?}", rhs_val, val);
-                }
+    /// Declares a variable in its slot in the innermost scope.
+    pub fn declare_local(&mut self, name: &str, index: usize, val: Atom, ty: Option<Type>) {
+        check_annotation(name, &val, ty);
+        let slots = &mut self.scopes.last_mut().unwrap().slots;
+        match slots.get_mut(index) {
+            Some(old) => *old = val,
+            None => {
+                // slots are declared in order, once the statements
+                // before them have run
+                debug_assert_eq!(index, slots.len(), "{} declared out of order", name);
+                slots.push(val);
             }
-            (None, false) => {
-                panic!("Uninitialized variable {}", dec.lhs)
+        }
+    }
+
+    /// Gives an existing variable a new value of the same type, which
+    /// is added to or subtracted from the old one for `+=` and `-=`.
+    pub fn assign(
+        &mut self,
+        name: &str,
+        slot: Option<Slot>,
+        old_val: Atom,
+        rhs_val: Atom,
+        plus_or_minus: Option<bool>,
+    ) {
+        let new_val = assigned_value(old_val, rhs_val, plus_or_minus);
+        match slot {
+            Some(slot) => self.set_local(slot, new_val),
+            None => self.modify_variable(name, new_val),
+        }
+    }
+}
+
+/// The value a variable gets from an assignment, which has to be of
+/// the type it had.
+pub fn assigned_value(old_val: Atom, rhs_val: Atom, plus_or_minus: Option<bool>) -> Atom {
+    if !old_val.same_type(&rhs_val) {
+        panic!("Cannot assign {:?} to {:?}", rhs_val, Some(old_val));
+    }
+    match plus_or_minus {
+        Some(true) => old_val + rhs_val,
+        Some(false) => old_val - rhs_val,
+        None => rhs_val,
+    }
+}
+
+fn check_annotation(name: &str, val: &Atom, ty: Option<Type>) {
+    if let Some(ty) = ty {
+        if !ty.matches(val) {
+            panic!(
+                "Cannot assign {} to {}, which is declared as {}",
+                val.type_name(),
+                name,
+                ty
+            );
+        }
+    }
+}
+
+/// Variables declared by name. Each keeps the index it was first
+/// declared at, so compiled code can find a global once and then go
+/// straight to it.
+#[derive(Default, Debug, Clone)]
+pub struct Vars {
+    indexes: BTreeMap<String, usize>,
+    values: Vec<Atom>,
+}
+
+impl Vars {
+    pub fn get(&self, name: &str) -> Option<&Atom> {
+        self.indexes.get(name).map(|&i| &self.values[i])
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Atom> {
+        match self.indexes.get(name) {
+            Some(&i) => Some(&mut self.values[i]),
+            None => None,
+        }
+    }
+
+    /// Declares a variable, or gives an existing one a new value
+    /// without moving it.
+    pub fn insert(&mut self, name: String, val: Atom) {
+        match self.indexes.get(&name) {
+            Some(&i) => self.values[i] = val,
+            None => {
+                self.indexes.insert(name, self.values.len());
+                self.values.push(val);
             }
         }
     }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.indexes.contains_key(name)
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.indexes.get(name).copied()
+    }
+
+    pub fn at(&self, index: usize) -> &Atom {
+        &self.values[index]
+    }
+
+    pub fn at_mut(&mut self, index: usize) -> &mut Atom {
+        &mut self.values[index]
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.indexes.keys()
+    }
+
+    /// The variables sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Atom)> + Clone {
+        self.indexes
+            .iter()
+            .map(move |(name, &i)| (name, &self.values[i]))
+    }
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Scope {
-    pub vars: BTreeMap<String, Atom>,
+    pub vars: Vars,
+    /// The values of the variables the resolver gave a slot, in the
+    /// order they're declared
+    pub slots: Vec<Atom>,
+    /// The names of the slots, for code that looks them up by name
+    pub locals: Rc<Vec<String>>,
+}
+
+impl Scope {
+    pub fn new(locals: Rc<Vec<String>>) -> Self {
+        Scope {
+            vars: Vars::default(),
+            slots: Vec::with_capacity(locals.len()),
+            locals,
+        }
+    }
+
+    // slots that haven't been declared yet aren't in scope
+    fn slot_of(&self, name: &str) -> Option<usize> {
+        self.locals
+            .iter()
+            .take(self.slots.len())
+            .position(|n| n == name)
+    }
+
+    // inlined since looking up a variable can check every scope
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Atom> {
+        match self.slot_of(name) {
+            Some(i) => Some(&self.slots[i]),
+            None => self.vars.get(name),
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Atom> {
+        match self.slot_of(name) {
+            Some(i) => Some(&mut self.slots[i]),
+            None => self.vars.get_mut(name),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +323,10 @@ pub struct Declaration {
     pub rhs: S,
     pub alias: bool,
     pub plus_or_minus: Option<bool>,
+    /// The annotation on a `let`, checked when it runs
+    pub ty: Option<Type>,
+    /// Where the variable is, if the resolver found it
+    pub slot: Option<Slot>,
 }
 
 #[derive(Debug, Clone)]
@@ -102,6 +342,15 @@ pub struct While {
     pub loop_block: Block,
 }
 
+/// `import "path"`, or `import name from "path"`
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub name: Option<String>,
+    pub path: String,
+    /// Where the module goes, if it's imported inside a block
+    pub slot: Option<Slot>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Stmt {
     ExprStmt(S),
@@ -111,14 +360,18 @@ pub enum Stmt {
     WhileStmt(While),
     Block(Block),
     Break,
+    Import(Import),
 }
 
 impl Stmt {
-    pub fn execute(self, state: &mut State) -> Option<Atom> {
+    pub fn execute(&self, state: &mut State) -> Option<Atom> {
         match self {
-            Stmt::ExprStmt(expr) => Some(eval_expr(&expr, state)),
+            Stmt::ExprStmt(expr) => Some(eval_expr(expr, state)),
             Stmt::PrintStmt(expr) => {
-                println!("{}", eval_expr(&expr, state));
+                let val = eval_expr(expr, state);
+                if !state.quiet {
+                    println!("{}", val);
+                }
                 None
             }
             Stmt::Dec(dec) => {
@@ -128,25 +381,22 @@ impl Stmt {
             Stmt::IfStmt(if_data) => {
                 let If {
                     cond,
-                    mut then_block,
-                    mut else_block,
+                    then_block,
+                    else_block,
                 } = if_data;
 
-                if eval_expr(&cond, state) == Atom::Bool(true) {
+                if eval_expr(cond, state) == Atom::Bool(true) {
                     then_block.execute(state)
                 } else {
                     else_block.execute(state)
                 }
             }
             Stmt::WhileStmt(while_data) => {
-                let While {
-                    cond,
-                    mut loop_block,
-                } = while_data;
+                let While { cond, loop_block } = while_data;
 
                 let mut res = None;
 
-                while eval_expr(&cond, state) == Atom::Bool(true) {
+                while eval_expr(cond, state) == Atom::Bool(true) {
                     res = loop_block.execute(state);
                     if matches!(res, Some(Atom::Break)) {
                         res = None;
@@ -156,36 +406,135 @@ impl Stmt {
 
                 res
             }
-            Stmt::Block(mut b) => b.execute(state),
+            Stmt::Block(b) => b.execute(state),
             Stmt::Break => Some(Atom::Break),
+            Stmt::Import(import) => {
+                module::declare_import(import, state);
+                None
+            }
+        }
+    }
+}
+
+// writes the statements of a block on their own lines below the
+// statement they belong to
+fn write_body(f: &mut fmt::Formatter<'_>, block: &Block, indent: &str) -> fmt::Result {
+    for line in block.to_string().lines() {
+        write!(f, "\n{}{}", indent, line)?;
+    }
+    Ok(())
+}
+
+/// Statements are shown as s-expressions, like expressions are.
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::ExprStmt(expr) => write!(f, "{}", expr),
+            Stmt::PrintStmt(expr) => write!(f, "(print {})", expr),
+            Stmt::Dec(Declaration {
+                lhs,
+                rhs: S::Atom(Atom::Function(fn_data)),
+                ..
+            }) => {
+                let args: Vec<String> = fn_data
+                    .arg_names
+                    .iter()
+                    .zip(&fn_data.arg_types)
+                    .map(|(arg, ty)| match ty {
+                        Some(ty) => format!("{}: {}", arg, ty),
+                        None => arg.clone(),
+                    })
+                    .collect();
+                write!(f, "(fn {} ({})", lhs, args.join(" "))?;
+                if let Some(ty) = fn_data.return_type {
+                    write!(f, " -> {}", ty)?;
+                }
+                write_body(f, &fn_data.fn_block, "  ")?;
+                write!(f, ")")
+            }
+            Stmt::Dec(dec) => {
+                let op = match (dec.alias, dec.plus_or_minus) {
+                    (true, _) => "let",
+                    (false, Some(true)) => "+=",
+                    (false, Some(false)) => "-=",
+                    (false, None) => "=",
+                };
+                match dec.ty {
+                    Some(ty) => write!(f, "({} {}: {} {})", op, dec.lhs, ty, dec.rhs),
+                    None => write!(f, "({} {} {})", op, dec.lhs, dec.rhs),
+                }
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                write!(f, "(if {}", cond)?;
+                write_body(f, then_block, "  ")?;
+                if !else_block.statements.is_empty() {
+                    write!(f, "\n  (else")?;
+                    write_body(f, else_block, "    ")?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                write!(f, "(while {}", cond)?;
+                write_body(f, loop_block, "  ")?;
+                write!(f, ")")
+            }
+            Stmt::Block(block) => {
+                write!(f, "(block")?;
+                write_body(f, block, "  ")?;
+                write!(f, ")")
+            }
+            Stmt::Break => write!(f, "(break)"),
+            Stmt::Import(Import {
+                name: Some(name),
+                path,
+                ..
+            }) => {
+                write!(f, "(import {} {:?})", name, path)
+            }
+            Stmt::Import(Import {
+                name: None, path, ..
+            }) => write!(f, "(import {:?})", path),
         }
     }
 }
 
 #[cfg(test)]
 mod stmt_tests {
-    use crate::run_file;
     use crate::Atom;
-    use crate::State;
+    use crate::{Backend, Interpreter};
+
+    // every file is run by both the tree-walker and the VM
+    const BACKENDS: [Backend; 2] = [Backend::TreeWalk, Backend::Vm];
 
     macro_rules! test_files {
         () => {};
         ( $fn_name:ident, $file:expr => $expected:expr; $($tail:tt)* ) => {
             #[test]
             fn $fn_name() {
-                let mut top_state = State::default();
-                let output = run_file(format!("test_files/{}", $file), &mut top_state).unwrap();
-                assert_eq!(output, $expected);
+                for backend in BACKENDS {
+                    let mut interp = Interpreter::new();
+                    interp.set_backend(backend);
+                    let output = interp.eval_file(format!("test_files/{}", $file)).unwrap();
+                    assert_eq!(output, $expected, "with {:?}", backend);
+                }
             }
 
             test_files!($($tail)*);
         };
         ( $fn_name:ident, $file:expr; $($tail:tt)* ) => {
             #[test]
-            #[should_panic]
             fn $fn_name() {
-                let mut top_state = State::default();
-                run_file(format!("test_files/{}", $file), &mut top_state).unwrap();
+                for backend in BACKENDS {
+                    let mut interp = Interpreter::new();
+                    interp.set_backend(backend);
+                    let res = interp.eval_file(format!("test_files/{}", $file));
+                    assert!(res.is_err(), "{} ran with {:?}", $file, backend);
+                }
             }
 
             test_files!($($tail)*);
@@ -207,7 +556,28 @@ mod stmt_tests {
         loop_break, "loop_break.slang" => Some(Atom::Int(5));
         nested_loop_break, "nested_loop_break.slang" => Some(Atom::Int(25));
         recur1, "recursion01.slang" => Some(Atom::Int(987));
+        array, "array.slang" => Some(Atom::Int(6));
+        annotations, "annotations.slang" => Some(Atom::Str("total: 12".to_string()));
+        modules, "modules.slang" => Some(Atom::Int(316));
         error1, "error1.slang";
         scope_typecheck, "scope_typecheck.slang";
+        annotation_runtime, "annotation_runtime.slang";
+        import_cycle, "import_cycle.slang";
     );
+
+    #[test]
+    fn test_display() {
+        let code = "fn f(a: int, b) -> int {\n a + b\n}\nlet x = 1\nwhile (x < 3) {\n x += 1\n if (x == 2) {\n break\n } else {\n print(x)\n }\n}";
+        let expected = "\
+(fn f (a: int b) -> int
+  (+ (Local a 0 0) (Local b 0 1)))
+(let x 1)
+(while (< (Identifier x) 3)
+  (+= x 1)
+  (if (== (Identifier x) 2)
+    (break)
+    (else
+      (print (Identifier x)))))";
+        assert_eq!(crate::parse(code).unwrap().to_string(), expected);
+    }
 }
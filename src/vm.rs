@@ -0,0 +1,373 @@
+//! A bytecode compiler and a stack machine to run what it compiles,
+//! which is an alternative to walking the syntax tree. Code gives the
+//! same results either way.
+//!
+//! A script finds each global it uses by name once, and indexes
+//! straight into the global scope after that. Functions are compiled
+//! the first time they're called, wherever they were declared, which
+//! includes the prelude and imported modules.
+
+use crate::eval::atom::{Atom, FunctionData};
+use crate::eval::{call_native, check_return, enter_function};
+use crate::module;
+use crate::parser::S;
+use crate::statement::{assigned_value, Scope, State};
+
+mod chunk;
+mod compiler;
+
+pub use chunk::{CallSite, Chunk, Instr, Var};
+pub use compiler::{compile, compile_function};
+
+/// Runs a chunk in the innermost scope of `state` and gives back its
+/// value, which is the value of the last statement that ran.
+pub fn execute(chunk: &Chunk, state: &mut State) -> Option<Atom> {
+    let depth = state.scopes.len();
+    let mut stack: Vec<Atom> = Vec::new();
+    let mut result = None;
+    let mut ip = 0;
+    // where each of the chunk's names is in the global scope, once
+    // it's been looked up
+    let mut globals: Vec<Option<usize>> = Vec::new();
+
+    macro_rules! pop {
+        () => {
+            stack.pop().expect("stack underflow")
+        };
+    }
+    macro_rules! global {
+        ($i:expr) => {{
+            let i = $i as usize;
+            if globals.is_empty() {
+                globals.resize(chunk.names.len(), None);
+            }
+            if globals[i].is_none() {
+                globals[i] = state.scopes[0].vars.index_of(&chunk.names[i]);
+            }
+            globals[i]
+        }};
+    }
+    macro_rules! binary {
+        (|$a:ident, $b:ident| $res:expr) => {{
+            let $b = pop!();
+            let $a = pop!();
+            stack.push($res);
+        }};
+    }
+
+    while let Some(&instr) = chunk.code.get(ip) {
+        ip += 1;
+        match instr {
+            Instr::Constant(i) => stack.push(chunk.constants[i as usize].clone()),
+            Instr::Get(i) => {
+                let name = &chunk.names[i as usize];
+                match state.get_variable(name) {
+                    Some(a) => stack.push(a.clone()),
+                    None => panic!("Variable {} is undefined", name),
+                }
+            }
+            Instr::GetOld(i) => {
+                let name = &chunk.names[i as usize];
+                match state.get_variable(name) {
+                    Some(a) => stack.push(a.clone()),
+                    None => panic!("Uninitialized variable {}", name),
+                }
+            }
+            Instr::Declare(i, ty) => {
+                let val = pop!();
+                state.declare_value(&chunk.names[i as usize], val, ty);
+            }
+            Instr::Assign(i, plus_or_minus) => {
+                let rhs_val = pop!();
+                let old_val = pop!();
+                state.assign(
+                    &chunk.names[i as usize],
+                    None,
+                    old_val,
+                    rhs_val,
+                    plus_or_minus,
+                );
+            }
+            Instr::GetGlobal(i) => match global!(i) {
+                Some(g) => stack.push(state.scopes[0].vars.at(g).clone()),
+                None => panic!("Variable {} is undefined", chunk.names[i as usize]),
+            },
+            Instr::GetOldGlobal(i) => match global!(i) {
+                Some(g) => stack.push(state.scopes[0].vars.at(g).clone()),
+                None => panic!("Uninitialized variable {}", chunk.names[i as usize]),
+            },
+            Instr::AssignGlobal(i, plus_or_minus) => {
+                let rhs_val = pop!();
+                let old_val = pop!();
+                let new_val = assigned_value(old_val, rhs_val, plus_or_minus);
+                // GetOldGlobal found it already
+                let g = global!(i).expect("assigned to an undeclared global");
+                *state.scopes[0].vars.at_mut(g) = new_val;
+            }
+            Instr::GetLocal(i) => {
+                let local = &chunk.locals[i as usize];
+                match state.get_local(local.slot) {
+                    Some(a) => stack.push(a.clone()),
+                    None => panic!("Variable {} is undefined", local.name),
+                }
+            }
+            Instr::GetOldLocal(i) => {
+                let local = &chunk.locals[i as usize];
+                match state.get_local(local.slot) {
+                    Some(a) => stack.push(a.clone()),
+                    None => panic!("Uninitialized variable {}", local.name),
+                }
+            }
+            Instr::DeclareLocal(i, ty) => {
+                let local = &chunk.locals[i as usize];
+                let val = pop!();
+                state.declare_local(&local.name, local.slot.index, val, ty);
+            }
+            Instr::AssignLocal(i, plus_or_minus) => {
+                let local = &chunk.locals[i as usize];
+                let rhs_val = pop!();
+                let old_val = pop!();
+                state.assign(
+                    &local.name,
+                    Some(local.slot),
+                    old_val,
+                    rhs_val,
+                    plus_or_minus,
+                );
+            }
+            Instr::Array(n) => {
+                let xs = stack.split_off(stack.len() - n as usize);
+                stack.push(Atom::Array(xs.into_iter().map(S::Atom).collect()));
+            }
+            Instr::Call(i) => {
+                let site = &chunk.calls[i as usize];
+                let args = stack.split_off(stack.len() - site.args.len());
+                stack.push(call_site(site, args, chunk, state));
+            }
+            Instr::Access(i) => {
+                let rhs = &chunk.exprs[i as usize];
+                let res = match pop!() {
+                    Atom::Module(m) => m.access(rhs, state),
+                    a => a.access(rhs),
+                };
+                stack.push(res);
+            }
+            Instr::Format(i) => {
+                let a = pop!();
+                stack.push(Atom::Str(chunk.formats[i as usize].format(&a)));
+            }
+            Instr::Import(i) => module::declare_import(&chunk.imports[i as usize], state),
+            Instr::Add => binary!(|a, b| a + b),
+            Instr::Subtract => binary!(|a, b| a - b),
+            Instr::Multiply => binary!(|a, b| a * b),
+            Instr::Divide => binary!(|a, b| a / b),
+            Instr::FloorDivide => binary!(|a, b| a.floor_div(&b)),
+            Instr::Power => binary!(|a, b| a.pow(&b)),
+            Instr::Modulo => binary!(|a, b| a.modulus(&b)),
+            Instr::BitAnd => binary!(|a, b| a & b),
+            Instr::BitOr => binary!(|a, b| a | b),
+            Instr::BitXor => binary!(|a, b| a ^ b),
+            Instr::ShiftLeft => binary!(|a, b| a << b),
+            Instr::ShiftRight => binary!(|a, b| a >> b),
+            Instr::Equal => binary!(|a, b| Atom::Bool(a == b)),
+            Instr::NotEqual => binary!(|a, b| Atom::Bool(a != b)),
+            Instr::Less => binary!(|a, b| Atom::Bool(a < b)),
+            Instr::Greater => binary!(|a, b| Atom::Bool(a > b)),
+            Instr::And => binary!(|a, b| a.and(&b)),
+            Instr::Or => binary!(|a, b| a.or(&b)),
+            Instr::Index => binary!(|a, b| a.index(&b)),
+            Instr::Negate => {
+                let a = pop!();
+                stack.push(a.negate());
+            }
+            Instr::Slice(has_end) => {
+                let end = if has_end { Some(pop!()) } else { None };
+                let start = pop!();
+                let a = pop!();
+                stack.push(a.slice(&start, end.as_ref()));
+            }
+            Instr::Print => {
+                let val = pop!();
+                if !state.quiet {
+                    println!("{}", val);
+                }
+            }
+            Instr::SetResult => result = Some(pop!()),
+            Instr::ClearResult => result = None,
+            Instr::PushScope(i) => {
+                let locals = chunk.scopes[i as usize].clone();
+                state.scopes.push(Scope::new(locals));
+            }
+            Instr::PopScope => {
+                state.scopes.pop();
+            }
+            Instr::Jump(target) => ip = target as usize,
+            Instr::JumpIfFalse(target) => {
+                if pop!() != Atom::Bool(true) {
+                    ip = target as usize;
+                }
+            }
+        }
+    }
+
+    state.scopes.truncate(depth);
+    result
+}
+
+/// Calls a function with already evaluated arguments, in a new scope
+/// on top of the caller's, compiling it if it hasn't been yet.
+pub fn call(f: &FunctionData, args: Vec<Atom>, state: &mut State) -> Option<Atom> {
+    let code = f.code();
+    enter_function(f, args, state);
+    let res = execute(&code, state);
+    state.scopes.pop();
+
+    check_return(&f.return_type, res)
+}
+
+fn call_site(site: &CallSite, mut args: Vec<Atom>, chunk: &Chunk, state: &mut State) -> Atom {
+    let res = match state.get_variable(&site.name) {
+        Some(Atom::Function(f)) => {
+            let f = f.clone();
+            call(&f, args, state)
+        }
+        Some(Atom::NativeFunction(native)) => {
+            let native = native.clone();
+            let res = call_native(&native, &mut args, state);
+
+            // write back the arguments the function modifies, like the
+            // tree-walker does
+            for &i in native.mutates {
+                let val = args[i].clone();
+                match site.args[i] {
+                    Some(Var::Name(name)) => {
+                        state.modify_variable(&chunk.names[name as usize], val)
+                    }
+                    Some(Var::Local(local)) => {
+                        state.set_local(chunk.locals[local as usize].slot, val)
+                    }
+                    None => {}
+                }
+            }
+            Some(res)
+        }
+        Some(v) => panic!("{} is not a function", v),
+        None => panic!("Function {} is undefined", site.name),
+    };
+
+    match res {
+        Some(a) => a,
+        None => panic!("Function {} didn't return a value", site.name),
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::*;
+    use crate::{Backend, Interpreter};
+
+    fn eval(code: &str) -> Option<Atom> {
+        let mut interp = Interpreter::new();
+        interp.set_backend(Backend::Vm);
+        interp.eval_str(code).unwrap()
+    }
+
+    #[test]
+    fn test_instr_size() {
+        assert!(std::mem::size_of::<Instr>() <= 8);
+    }
+
+    #[test]
+    fn test_compile() {
+        let block = crate::parse("let x = 1\nx += 2\nx").unwrap();
+        let chunk = compile(&block);
+        assert_eq!(
+            chunk.code,
+            vec![
+                Instr::Constant(0),
+                Instr::Declare(0, None),
+                Instr::ClearResult,
+                Instr::GetOldGlobal(0),
+                Instr::Constant(1),
+                Instr::AssignGlobal(0, Some(true)),
+                Instr::ClearResult,
+                Instr::GetGlobal(0),
+                Instr::SetResult,
+            ]
+        );
+        assert_eq!(chunk.constants, vec![Atom::Int(1), Atom::Int(2)]);
+
+        // equal numbers of different types get their own constants
+        let chunk = compile(&crate::parse("[1, 1.0, 1, 1.0]").unwrap());
+        assert_eq!(format!("{:?}", chunk.constants), "[Int(1), Float(1.0)]");
+    }
+
+    #[test]
+    fn test_vm() {
+        assert_eq!(eval("1 + 2 * 3"), Some(Atom::Int(7)));
+        assert_eq!(
+            eval("let i = 0\nwhile (true) {\n    i += 1\n    if (i == 5) {\n        break\n    }\n}\ni"),
+            Some(Atom::Int(5))
+        );
+        assert_eq!(
+            eval("fn fib(n) {\n    if (n < 2) {\n        n\n    } else {\n        fib(n - 1) + fib(n - 2)\n    }\n}\nfib(15)"),
+            Some(Atom::Int(610))
+        );
+        // compiled functions passed to builtins run in the VM too
+        assert_eq!(
+            eval("fn double(x) { x * 2 }\nfn add(a, b) { a + b }\nreduce(map([1, 2, 3], double), 0, add)"),
+            Some(Atom::Int(12))
+        );
+        assert_eq!(eval("let a = [1]\npush(a, 2)\na"), eval("[1, 2]"));
+    }
+
+    #[test]
+    fn test_globals() {
+        // a global keeps its place when it's declared again or changed
+        // by a function
+        let code = "let x = 0\nfn inc() {\n x += 1\n x\n}\nwhile (x < 3) {\n inc()\n}\nlet x = x * 10\nx += 1\nx";
+        assert_eq!(eval(code), Some(Atom::Int(31)));
+
+        let mut interp = Interpreter::new();
+        interp.set_backend(Backend::Vm);
+        let err = interp.eval_str("let y = 1\ny = z").unwrap_err();
+        assert_eq!(err.to_string(), "Variable z is undefined");
+    }
+
+    #[test]
+    fn test_prelude_compiled() {
+        let mut interp = Interpreter::new();
+        interp.set_backend(Backend::Vm);
+        assert_eq!(
+            interp.eval_str("len(range(0, 3))").unwrap(),
+            Some(Atom::Int(3))
+        );
+        match interp.get_global("range") {
+            Some(Atom::Function(f)) => assert!(f.code.borrow().is_some()),
+            v => panic!("range is {:?}", v),
+        }
+    }
+
+    // the backends used to disagree about these
+    #[test]
+    fn test_same_errors() {
+        let cases = [
+            (
+                "fn f() {\n let y = 1\n}\nlet x = f()",
+                "Function f didn't return a value",
+            ),
+            (
+                "fn stop() {\n break\n}\nlet i = 0\nwhile (i < 5) {\n i += 1\n stop()\n}\ni",
+                "break outside of a loop on line 2",
+            ),
+        ];
+        for backend in [Backend::TreeWalk, Backend::Vm] {
+            for (code, msg) in cases {
+                let mut interp = Interpreter::new();
+                interp.set_backend(backend);
+                let err = interp.eval_str(code).unwrap_err();
+                assert_eq!(err.to_string(), msg, "with {:?}", backend);
+            }
+        }
+    }
+}
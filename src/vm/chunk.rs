@@ -0,0 +1,165 @@
+use std::rc::Rc;
+
+use crate::checker::Type;
+use crate::eval::atom::{Atom, FormatSpec, Local};
+use crate::parser::S;
+use crate::statement::Import;
+
+/// One bytecode instruction. Operands are indexes into the tables of
+/// the [`Chunk`] the instruction is in, which keeps every instruction
+/// to 8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    /// Pushes `constants[i]`
+    Constant(u32),
+    /// Pushes the value of the variable `names[i]`
+    Get(u32),
+    /// Pushes the value of `names[i]` before it's assigned to, which
+    /// has to exist already
+    GetOld(u32),
+    /// Pops a value and declares `names[i]` with it in the innermost
+    /// scope, checking it against the annotation
+    Declare(u32, Option<Type>),
+    /// Pops the new value and the old one pushed by `GetOld` and
+    /// assigns to `names[i]`, adding or subtracting for `+=` and `-=`
+    Assign(u32, Option<bool>),
+    /// The same as `Get`, `GetOld` and `Assign`, for the global
+    /// `names[i]`, which is only looked up by name the first time
+    GetGlobal(u32),
+    GetOldGlobal(u32),
+    AssignGlobal(u32, Option<bool>),
+    /// The same as `Get`, `GetOld`, `Declare` and `Assign`, for the
+    /// variable in the slot `locals[i]`
+    GetLocal(u32),
+    GetOldLocal(u32),
+    DeclareLocal(u32, Option<Type>),
+    AssignLocal(u32, Option<bool>),
+    /// Pops `n` values and pushes them as an array
+    Array(u32),
+    /// Calls `calls[i]` with the arguments on top of the stack
+    Call(u32),
+    /// Pops a value and evaluates `exprs[i]`, a name or call, on it
+    Access(u32),
+    /// Pops a value and pushes it formatted with `formats[i]`
+    Format(u32),
+    /// Runs `imports[i]`
+    Import(u32),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Power,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    And,
+    Or,
+    Negate,
+    Index,
+    /// Pops a start, and an end if it's true, then the value to slice
+    Slice(bool),
+    /// Pops a value and prints it
+    Print,
+    /// Pops a value into the result, which is what a block evaluates to
+    SetResult,
+    ClearResult,
+    /// Pushes a scope with the slots named by `scopes[i]`
+    PushScope(u32),
+    PopScope,
+    Jump(u32),
+    /// Pops a value and jumps unless it's `true`
+    JumpIfFalse(u32),
+}
+
+/// A variable, which is `names[i]` or `locals[i]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Var {
+    Name(u32),
+    Local(u32),
+}
+
+/// A function call in the source, with the arguments that are plain
+/// variables so that natives can write back to them.
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    pub name: String,
+    pub args: Vec<Option<Var>>,
+}
+
+/// Compiled code, with the values and names it refers to.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Instr>,
+    pub constants: Vec<Atom>,
+    pub names: Vec<String>,
+    pub locals: Vec<Local>,
+    /// The names of the slots of each block's scope
+    pub scopes: Vec<Rc<Vec<String>>>,
+    pub calls: Vec<CallSite>,
+    pub exprs: Vec<S>,
+    pub formats: Vec<FormatSpec>,
+    pub imports: Vec<Import>,
+}
+
+impl Chunk {
+    pub fn constant(&mut self, val: Atom) -> u32 {
+        // functions never compare equal, so they're always added
+        let i = match self.constants.iter().position(|c| same_constant(c, &val)) {
+            Some(i) => i,
+            None => {
+                self.constants.push(val);
+                self.constants.len() - 1
+            }
+        };
+        i as u32
+    }
+
+    pub fn name(&mut self, name: &str) -> u32 {
+        let i = match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        };
+        i as u32
+    }
+
+    pub fn local(&mut self, local: &Local) -> u32 {
+        let i = match self.locals.iter().position(|l| l == local) {
+            Some(i) => i,
+            None => {
+                self.locals.push(local.clone());
+                self.locals.len() - 1
+            }
+        };
+        i as u32
+    }
+
+    pub fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    pub fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        match &mut self.code[at] {
+            Instr::Jump(t) | Instr::JumpIfFalse(t) => *t = target,
+            instr => unreachable!("{:?} isn't a jump", instr),
+        }
+    }
+}
+
+// 1 and 1.0 are equal but can't share a constant
+fn same_constant(a: &Atom, b: &Atom) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b) && a == b
+}
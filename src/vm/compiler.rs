@@ -0,0 +1,274 @@
+use super::chunk::{CallSite, Chunk, Instr, Var};
+use crate::block::Block;
+use crate::eval::atom::{Atom, FunctionCall, Local};
+use crate::parser::{Op, S};
+use crate::statement::{Declaration, If, Stmt, While};
+
+struct Loop {
+    // how many scopes deep the loop is, so break knows how many
+    // scopes to leave
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler {
+    chunk: Chunk,
+    depth: usize,
+    loops: Vec<Loop>,
+    // whether the names the resolver left alone are globals, which
+    // they are outside functions
+    globals: bool,
+}
+
+/// Compiles a script, which runs in the global scope. Like the
+/// tree-walker, the chunk's result is the value of the last statement
+/// that ran.
+pub fn compile(block: &Block) -> Chunk {
+    Compiler::new(true).run(block)
+}
+
+/// Compiles a function body, which runs in the scope its call pushes.
+/// Variables it uses without declaring come from whoever calls it, so
+/// they're looked up by name.
+pub fn compile_function(block: &Block) -> Chunk {
+    Compiler::new(false).run(block)
+}
+
+impl Compiler {
+    fn new(globals: bool) -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            depth: 0,
+            loops: Vec::new(),
+            globals,
+        }
+    }
+
+    fn run(mut self, block: &Block) -> Chunk {
+        self.statements(block);
+        self.chunk
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.chunk.emit(instr)
+    }
+
+    fn statements(&mut self, block: &Block) {
+        if block.statements.is_empty() {
+            self.emit(Instr::ClearResult);
+        }
+        for stmt in &block.statements {
+            self.stmt(stmt);
+        }
+    }
+
+    fn scoped_block(&mut self, block: &Block) {
+        self.chunk.scopes.push(block.locals.clone());
+        let i = self.chunk.scopes.len() - 1;
+        self.emit(Instr::PushScope(i as u32));
+        self.depth += 1;
+        self.statements(block);
+        self.depth -= 1;
+        self.emit(Instr::PopScope);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::ExprStmt(expr) => {
+                self.expr(expr);
+                self.emit(Instr::SetResult);
+            }
+            Stmt::PrintStmt(expr) => {
+                self.expr(expr);
+                self.emit(Instr::Print);
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::Dec(dec) => {
+                self.declaration(dec);
+                self.emit(Instr::ClearResult);
+            }
+            Stmt::IfStmt(If {
+                cond,
+                then_block,
+                else_block,
+            }) => {
+                self.expr(cond);
+                let to_else = self.emit(Instr::JumpIfFalse(0));
+                self.scoped_block(then_block);
+                let to_end = self.emit(Instr::Jump(0));
+                self.chunk.patch(to_else);
+                self.scoped_block(else_block);
+                self.chunk.patch(to_end);
+            }
+            Stmt::WhileStmt(While { cond, loop_block }) => {
+                // a loop that doesn't run has no value
+                self.emit(Instr::ClearResult);
+                let start = self.chunk.code.len() as u32;
+                self.expr(cond);
+                let to_end = self.emit(Instr::JumpIfFalse(0));
+
+                self.loops.push(Loop {
+                    depth: self.depth,
+                    breaks: Vec::new(),
+                });
+                self.scoped_block(loop_block);
+                self.emit(Instr::Jump(start));
+
+                self.chunk.patch(to_end);
+                let finished = self.loops.pop().unwrap();
+                for at in finished.breaks {
+                    self.chunk.patch(at);
+                }
+            }
+            Stmt::Block(block) => self.scoped_block(block),
+            Stmt::Break => match self.loops.last() {
+                Some(l) => {
+                    let scopes = self.depth - l.depth;
+                    self.emit(Instr::ClearResult);
+                    for _ in 0..scopes {
+                        self.emit(Instr::PopScope);
+                    }
+                    let at = self.emit(Instr::Jump(0));
+                    self.loops.last_mut().unwrap().breaks.push(at);
+                }
+                // the checker reports these
+                None => panic!("break outside of a loop"),
+            },
+            Stmt::Import(import) => {
+                self.chunk.imports.push(import.clone());
+                let i = self.chunk.imports.len() - 1;
+                self.emit(Instr::Import(i as u32));
+                self.emit(Instr::ClearResult);
+            }
+        }
+    }
+
+    fn declaration(&mut self, dec: &Declaration) {
+        match dec.slot {
+            Some(slot) => {
+                let local = self.chunk.local(&Local {
+                    name: dec.lhs.clone(),
+                    slot,
+                });
+                if dec.alias {
+                    self.expr(&dec.rhs);
+                    self.emit(Instr::DeclareLocal(local, dec.ty));
+                } else {
+                    self.emit(Instr::GetOldLocal(local));
+                    self.expr(&dec.rhs);
+                    self.emit(Instr::AssignLocal(local, dec.plus_or_minus));
+                }
+            }
+            None => {
+                let name = self.chunk.name(&dec.lhs);
+                if dec.alias {
+                    self.expr(&dec.rhs);
+                    self.emit(Instr::Declare(name, dec.ty));
+                } else if self.globals {
+                    self.emit(Instr::GetOldGlobal(name));
+                    self.expr(&dec.rhs);
+                    self.emit(Instr::AssignGlobal(name, dec.plus_or_minus));
+                } else {
+                    self.emit(Instr::GetOld(name));
+                    self.expr(&dec.rhs);
+                    self.emit(Instr::Assign(name, dec.plus_or_minus));
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, a: &S, b: &S, instr: Instr) {
+        self.expr(a);
+        self.expr(b);
+        self.emit(instr);
+    }
+
+    fn expr(&mut self, expr: &S) {
+        match expr {
+            S::Atom(Atom::Identifier(name)) => {
+                let name = self.chunk.name(name);
+                if self.globals {
+                    self.emit(Instr::GetGlobal(name));
+                } else {
+                    self.emit(Instr::Get(name));
+                }
+            }
+            S::Atom(Atom::Local(local)) => {
+                let local = self.chunk.local(local);
+                self.emit(Instr::GetLocal(local));
+            }
+            S::Atom(Atom::FnCall(FunctionCall { name, args })) => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        S::Atom(Atom::Identifier(name)) => Some(Var::Name(self.chunk.name(name))),
+                        S::Atom(Atom::Local(local)) => Some(Var::Local(self.chunk.local(local))),
+                        _ => None,
+                    })
+                    .collect();
+                self.chunk.calls.push(CallSite {
+                    name: name.clone(),
+                    args,
+                });
+                let i = self.chunk.calls.len() - 1;
+                self.emit(Instr::Call(i as u32));
+            }
+            S::Atom(Atom::Array(xs)) => {
+                for x in xs {
+                    self.expr(x);
+                }
+                self.emit(Instr::Array(xs.len() as u32));
+            }
+            S::Atom(a) => {
+                let i = self.chunk.constant(a.clone());
+                self.emit(Instr::Constant(i));
+            }
+            S::Cons(op, xs) => match (op, xs.as_slice()) {
+                (Op::Plus, [a, b, ..]) => self.binary(a, b, Instr::Add),
+                (Op::Minus, [a, b, ..]) => self.binary(a, b, Instr::Subtract),
+                (Op::Minus, [a]) | (Op::Negate, [a]) => {
+                    self.expr(a);
+                    self.emit(Instr::Negate);
+                }
+                (Op::Multiply, [a, b, ..]) => self.binary(a, b, Instr::Multiply),
+                (Op::Divide, [a, b, ..]) => self.binary(a, b, Instr::Divide),
+                (Op::FloorDivide, [a, b]) => self.binary(a, b, Instr::FloorDivide),
+                (Op::Power, [a, b]) => self.binary(a, b, Instr::Power),
+                (Op::BitAnd, [a, b]) => self.binary(a, b, Instr::BitAnd),
+                (Op::BitOr, [a, b]) => self.binary(a, b, Instr::BitOr),
+                (Op::BitXor, [a, b]) => self.binary(a, b, Instr::BitXor),
+                (Op::ShiftLeft, [a, b]) => self.binary(a, b, Instr::ShiftLeft),
+                (Op::ShiftRight, [a, b]) => self.binary(a, b, Instr::ShiftRight),
+                (Op::Equal, [a, b]) => self.binary(a, b, Instr::Equal),
+                (Op::NotEqual, [a, b]) => self.binary(a, b, Instr::NotEqual),
+                (Op::Less, [a, b]) => self.binary(a, b, Instr::Less),
+                (Op::Greater, [a, b]) => self.binary(a, b, Instr::Greater),
+                (Op::Mod, [a, b]) => self.binary(a, b, Instr::Modulo),
+                (Op::And, [a, b]) => self.binary(a, b, Instr::And),
+                (Op::Or, [a, b]) => self.binary(a, b, Instr::Or),
+                (Op::Indexing, [a, b]) => self.binary(a, b, Instr::Index),
+                (Op::Slice, [a, start]) => self.binary(a, start, Instr::Slice(false)),
+                (Op::Slice, [a, start, end]) => {
+                    self.expr(a);
+                    self.binary(start, end, Instr::Slice(true));
+                }
+                (Op::Access, [a, b]) => {
+                    self.expr(a);
+                    self.chunk.exprs.push(b.clone());
+                    let i = self.chunk.exprs.len() - 1;
+                    self.emit(Instr::Access(i as u32));
+                }
+                (Op::Format(spec), [a]) => {
+                    self.expr(a);
+                    self.chunk.formats.push(spec.clone());
+                    let i = self.chunk.formats.len() - 1;
+                    self.emit(Instr::Format(i as u32));
+                }
+                _ => panic!("invalid expr: {}", expr),
+            },
+        }
+    }
+}